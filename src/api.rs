@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use serde::Serialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response};
+use url::Url;
+
+use crate::boxer::{split_name, Boxer};
+use crate::output::ScanResult;
+use crate::storage::BOXREC_SCORE_MODEL;
+use crate::Session;
+
+// Something to send back instead of the normal response
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: &str) -> ApiError {
+        ApiError { status, message: message.to_string() }
+    }
+}
+
+impl From<Box<dyn Error>> for ApiError {
+    fn from(err: Box<dyn Error>) -> ApiError {
+        ApiError { status: 500, message: err.to_string() }
+    }
+}
+
+#[derive(Serialize)]
+struct BoxerInfo {
+    id: u32,
+    name: String,
+}
+
+impl BoxerInfo {
+    fn new(boxer: &Boxer) -> BoxerInfo {
+        BoxerInfo { id: *boxer.get_id(), name: boxer.get_name() }
+    }
+}
+
+#[derive(Serialize)]
+struct MatchupInfo {
+    fighter_one: BoxerInfo,
+    fighter_two: BoxerInfo,
+    score_one: Option<f32>,
+    score_two: Option<f32>,
+    win_percent_one: f32,
+    win_percent_two: f32,
+    warning: bool,
+    event_link: String,
+    event_date: Option<String>,
+}
+
+// Answers a single request, returning true if it asked for a rescan
+// Without a session (i.e. we couldn't log in to BoxRec) everything but rescanning is unavailable
+pub fn handle(request: Request, session: Option<&mut Session>) -> bool {
+    let mut rescan = false;
+    let result = match Url::parse(&format!("http://localhost{}", request.url())) {
+        Ok(url) => {
+            let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+            let path: Vec<&str> = url.path().split('/').filter(|part| !part.is_empty()).collect();
+            match (request.method(), path.as_slice()) {
+                (Method::Post, ["scan"]) => {
+                    rescan = true;
+                    Ok((202, json!({ "status": "Rescan queued" })))
+                },
+                (method, path) => match session {
+                    Some(session) => route(method, path, &params, session),
+                    None => Err(ApiError::new(503, "Not connected to BoxRec yet, try again after the next scan")),
+                },
+            }
+        },
+        Err(err) => Err(ApiError::new(400, &err.to_string())),
+    };
+
+    let (status, body) = match result {
+        Ok(reply) => reply,
+        Err(err) => (err.status, json!({ "error": err.message })),
+    };
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header("Content-Type: application/json".parse::<Header>().unwrap());
+    if let Err(err) = request.respond(response) {
+        eprintln!("Failed to respond to API request (Error: {})", err);
+    }
+    rescan
+}
+
+fn route(method: &Method, path: &[&str], params: &HashMap<String, String>, session: &mut Session) -> Result<(u16, serde_json::Value), ApiError> {
+    let value = match (method, path) {
+        (Method::Get, ["bouts"]) => json!(current_bouts(session)?),
+        (Method::Get, ["bouts", id]) => {
+            let id: i64 = id.parse().map_err(|_| ApiError::new(400, "Bout ID should be a number"))?;
+            let bout = current_bouts(session)?
+                .into_iter()
+                .find(|result| result.bout_id == id)
+                .ok_or_else(|| ApiError::new(404, "No current bout with that ID"))?;
+            json!(bout)
+        },
+        (Method::Get, ["boxers"]) => {
+            let name = params.get("name").ok_or_else(|| ApiError::new(400, "Missing name parameter"))?;
+            json!(find_boxers(session, name)?)
+        },
+        (Method::Get, ["boxers", id]) => {
+            let id: u32 = id.parse().map_err(|_| ApiError::new(400, "BoxRec ID should be a number"))?;
+            json!(BoxerInfo::new(&boxer_by_id(session, id)?))
+        },
+        (Method::Get, ["matchup"]) => {
+            let one = params.get("one").ok_or_else(|| ApiError::new(400, "Missing one parameter"))?;
+            let two = params.get("two").ok_or_else(|| ApiError::new(400, "Missing two parameter"))?;
+            json!(evaluate_matchup(session, one, two)?)
+        },
+        _ => return Err(ApiError::new(404, "Not found")),
+    };
+    Ok((200, value))
+}
+
+// Every unsettled bout, as it stood after the last scan
+pub fn current_bouts(session: &Session) -> Result<Vec<ScanResult>, Box<dyn Error>> {
+    let boxers = session.storage.load_boxers()?;
+    let mut results = Vec::new();
+    for metadata in session.storage.load_bouts()? {
+        let output = match metadata.id {
+            Some(id) => session.storage.latest_model_output(id, BOXREC_SCORE_MODEL)?,
+            None => None,
+        };
+        results.push(ScanResult::new(
+            &metadata,
+            boxers.get(&metadata.bout.fighter_one),
+            boxers.get(&metadata.bout.fighter_two),
+            output.as_ref(),
+        ));
+    }
+    Ok(results)
+}
+
+// Boxers we already know by that name, otherwise everyone BoxRec finds for it
+fn find_boxers(session: &mut Session, name: &str) -> Result<Vec<BoxerInfo>, ApiError> {
+    let mut known: Vec<BoxerInfo> = Vec::new();
+    for (alias, boxer) in session.storage.load_boxers()? {
+        if (alias.eq_ignore_ascii_case(name) || boxer.get_name().eq_ignore_ascii_case(name))
+            && !known.iter().any(|info| info.id == *boxer.get_id()) {
+            known.push(BoxerInfo::new(&boxer));
+        }
+    }
+    if !known.is_empty() {
+        return Ok(known);
+    }
+
+    let (forename, surname) = split_name(name).map_err(|err| ApiError::new(400, &err))?;
+    Ok(session.runtime.block_on(session.boxrec.search_boxers(&forename, &surname, false))?
+        .into_iter()
+        .map(|(name, id)| BoxerInfo { id, name })
+        .collect())
+}
+
+fn boxer_by_id(session: &mut Session, id: u32) -> Result<Boxer, ApiError> {
+    if let Some(boxer) = session.storage.load_boxers()?.into_values().find(|boxer| *boxer.get_id() == id) {
+        return Ok(boxer);
+    }
+    session.runtime.block_on(Boxer::new_by_id(&session.boxrec, id)).ok_or_else(|| ApiError::new(404, "Boxer not found on BoxRec"))
+}
+
+// A BoxRec ID, or a name that only matches one boxer
+fn resolve_boxer(session: &mut Session, query: &str) -> Result<Boxer, ApiError> {
+    if let Ok(id) = query.parse::<u32>() {
+        return boxer_by_id(session, id);
+    }
+    let mut found = find_boxers(session, query)?;
+    // BoxRec lists near misses too, so prefer an exact match if there's one
+    if let Some(index) = found.iter().position(|info| info.name.eq_ignore_ascii_case(query)) {
+        found = vec![found.swap_remove(index)];
+    }
+    match found.as_slice() {
+        [] => Err(ApiError::new(404, &format!("Couldn't find {} on BoxRec", query))),
+        [info] => {
+            let (forename, surname) = split_name(&info.name).map_err(|err| ApiError::new(500, &err))?;
+            Ok(Boxer::new(info.id, forename, surname))
+        },
+        _ => Err(ApiError::new(409, &format!(
+            "{} matches more than one boxer, use a BoxRec ID instead ({})",
+            query,
+            found.iter().map(|info| format!("{}: {}", info.id, info.name)).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+// Runs the BoxRec score model over a bout BoxRec has scheduled, without recording anything
+fn evaluate_matchup(session: &mut Session, one: &str, two: &str) -> Result<MatchupInfo, ApiError> {
+    let fighter_one = resolve_boxer(session, one)?;
+    let fighter_two = resolve_boxer(session, two)?;
+    let (event, matchup) = session.runtime.block_on(fighter_one.get_bout_scores(&session.boxrec, &fighter_two))
+        .map_err(|err| ApiError::new(404, &format!("Couldn't find the bout on BoxRec ({})", err)))?;
+
+    Ok(MatchupInfo {
+        fighter_one: BoxerInfo::new(&fighter_one),
+        fighter_two: BoxerInfo::new(&fighter_two),
+        score_one: matchup.scores.map(|(one, _)| one),
+        score_two: matchup.scores.map(|(_, two)| two),
+        win_percent_one: matchup.win_percent_one,
+        win_percent_two: matchup.win_percent_two,
+        warning: matchup.warning,
+        event_link: format!("https://boxrec.com{}", event.link),
+        event_date: event.date.map(|date| date.to_string()),
+    })
+}
@@ -0,0 +1,85 @@
+use crate::betfair::exchange;
+use crate::odds::{Bout, BoutOdds, Odds};
+
+// What a leg on the draw is called
+pub const DRAW: &str = "the draw";
+
+// One of the bets making up an arbitrage
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leg {
+    // The fighter's name, or DRAW
+    pub selection: String,
+    pub odds: Odds,
+    // Where the odds are on offer, if known
+    pub source: Option<String>,
+    pub stake: f32,
+    // The percentage of the leg's winnings the source takes, if it wins
+    pub commission: f32,
+}
+
+// Backing every outcome of a bout at prices long enough that more comes back than was staked, whoever wins
+pub struct Arbitrage {
+    pub legs: Vec<Leg>,
+    // The prices' implied chances added up, which is under 100 or it wouldn't be an arbitrage
+    pub book_percent: f32,
+}
+
+impl Arbitrage {
+    // Spreads the outlay across the bout's prices, if what comes back after commission beats the outlay
+    // by at least min_profit percent. Only the exchange takes commission
+    pub fn find(bout: &Bout, outlay: f32, exchange_commission: f32, min_profit: f32) -> Option<Arbitrage> {
+        let sources = bout.sources.as_ref();
+        let outcomes = [
+            (bout.fighter_one.as_str(), bout.odds.one_wins, sources.map(|sources| &sources.one_wins)),
+            (DRAW, bout.odds.draw, sources.map(|sources| &sources.draw)),
+            (bout.fighter_two.as_str(), bout.odds.two_wins, sources.map(|sources| &sources.two_wins)),
+        ];
+        let commission = |source: Option<&String>| {
+            if source.is_some_and(|source| source == exchange::SOURCE_NAME) { exchange_commission } else { 0f32 }
+        };
+        // Like the book percentage, but from what each price pays back once commission's taken
+        let book: f32 = outcomes.iter()
+            .map(|(_, odds, source)| 1f32 / net_decimal(*odds, commission(*source)))
+            .sum();
+        // Every leg pays back outlay / book, so that's all there is to the profit
+        if outlay / book - outlay < outlay * min_profit / 100f32 || book >= 1f32 {
+            return None;
+        }
+
+        Some(Arbitrage {
+            legs: outcomes.iter()
+                .map(|(selection, odds, source)| Leg {
+                    selection: selection.to_string(),
+                    odds: *odds,
+                    source: source.cloned(),
+                    // In proportion to the outcome's implied chance, so every leg pays out the same
+                    stake: outlay / (net_decimal(*odds, commission(*source)) * book),
+                    commission: commission(*source),
+                })
+                .collect(),
+            book_percent: book_percent(&bout.odds),
+        })
+    }
+}
+
+// Every outcome's implied chance added up, over 100 when there's a margin built in
+pub fn book_percent(odds: &BoutOdds) -> f32 {
+    odds.one_wins.as_percent() + odds.draw.as_percent() + odds.two_wins.as_percent()
+}
+
+// What's paid back for each unit staked if the bet wins, after commission on the winnings
+fn net_decimal(odds: Odds, commission: f32) -> f32 {
+    1f32 + (odds.as_decimal() - 1f32) * (1f32 - commission / 100f32)
+}
+
+pub fn outlay(legs: &[Leg]) -> f32 {
+    legs.iter().map(|leg| leg.stake).sum()
+}
+
+// What's made whoever wins, going by the leg that pays out least after commission
+pub fn profit(legs: &[Leg]) -> f32 {
+    let payout = legs.iter()
+        .map(|leg| leg.stake * net_decimal(leg.odds, leg.commission))
+        .fold(f32::INFINITY, f32::min);
+    payout - outlay(legs)
+}
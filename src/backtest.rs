@@ -0,0 +1,213 @@
+use std::error::Error;
+
+use crate::odds::{BoutOdds, Odds};
+use crate::boxrec::Winner;
+use crate::storage::{Storage, BOXREC_SCORE_MODEL};
+
+// What the Kelly bankroll starts at, flat stakes are always 1 unit
+const STARTING_BANKROLL: f32 = 100f32;
+
+// Everything we know about a bout that's been settled with a result
+struct HistoricalBout {
+    snapshots: Vec<(i64, BoutOdds)>,
+    model: Vec<(i64, f32)>,
+    winner: Winner,
+}
+
+// A bet the strategy would have placed, the first time a bout's edge crossed the threshold
+struct SimulatedBet {
+    placed_at: i64,
+    // Our model's chance of the side we backed winning, from 0 to 1
+    probability: f32,
+    odds: Odds,
+    closing_odds: Odds,
+    // None if the bet was void (no contest)
+    won: Option<bool>,
+}
+
+#[derive(Default)]
+struct StakingReport {
+    bets: usize,
+    wins: usize,
+    staked: f32,
+    profit: f32,
+    max_drawdown: f32,
+}
+
+impl StakingReport {
+    fn roi(&self) -> f32 {
+        if self.staked > 0f32 { 100f32 * self.profit / self.staked } else { 0f32 }
+    }
+
+    fn hit_rate(&self) -> f32 {
+        if self.bets > 0 { 100f32 * self.wins as f32 / self.bets as f32 } else { 0f32 }
+    }
+}
+
+// Replays stored odds and model outputs against settled results, to see how alerting at each
+// threshold would have done
+pub fn run_backtest(storage: &Storage, thresholds: &[f32], kelly_fraction: f32) -> Result<(), Box<dyn Error>> {
+    let bouts = storage.completed_bouts()?
+        .into_iter()
+        .map(|(id, winner)| Ok(HistoricalBout {
+            snapshots: storage.odds_history(id)?,
+            model: storage.model_history(id, BOXREC_SCORE_MODEL)?,
+            winner,
+        }))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    if bouts.is_empty() {
+        println!("No settled bouts with results to backtest against yet");
+        return Ok(());
+    }
+    println!("Backtesting against {} settled bout(s)\n", bouts.len());
+
+    print_brier_scores(&bouts);
+
+    println!("{:>9}  {:<6}  {:>4}  {:>8}  {:>8}  {:>9}  {:>9}  {:>7}",
+             "Threshold", "Stakes", "Bets", "Hit rate", "Profit", "ROI", "Drawdown", "CLV");
+    for threshold in thresholds {
+        let mut bets = bouts.iter()
+            .filter_map(|bout| simulate_bet(bout, *threshold))
+            .collect::<Vec<_>>();
+        bets.sort_by_key(|bet| bet.placed_at);
+        let clv = average_clv(&bets);
+
+        let flat = simulate_staking(&bets, |_, _| 1f32);
+        let kelly = simulate_staking(&bets, |bet, bankroll| {
+            kelly_fraction * kelly_stake(bet.probability, bet.odds.as_decimal()) * bankroll
+        });
+        for (name, report) in &[("Flat", flat), ("Kelly", kelly)] {
+            println!("{:>8}%  {:<6}  {:>4}  {:>7.1}%  {:>8.2}  {:>8.1}%  {:>9.2}  {:>6.1}%",
+                     threshold,
+                     name,
+                     report.bets,
+                     report.hit_rate(),
+                     report.profit,
+                     report.roi(),
+                     report.max_drawdown,
+                     clv);
+        }
+    }
+    println!("\nFlat stakes are 1 unit. Kelly stakes are {}x Kelly, starting from a bankroll of {} units",
+             kelly_fraction,
+             STARTING_BANKROLL);
+    Ok(())
+}
+
+// Walks through a bout's history, betting the first time our model beats Betfair by more than the threshold
+fn simulate_bet(bout: &HistoricalBout, threshold: f32) -> Option<SimulatedBet> {
+    let (_, closing) = bout.snapshots.last()?;
+
+    for (taken_at, odds) in &bout.snapshots {
+        // Only use what the model knew at the time
+        let win_percent_one = match bout.model.iter().rev().find(|(computed_at, _)| computed_at <= taken_at) {
+            Some((_, win_percent_one)) => *win_percent_one,
+            None => continue,
+        };
+
+        let (win_percent, odds, closing_odds, backed) =
+            if win_percent_one - odds.one_wins.as_percent() > threshold {
+                (win_percent_one, odds.one_wins, closing.one_wins, Winner::FighterOne)
+            } else if (100f32 - win_percent_one) - odds.two_wins.as_percent() > threshold {
+                (100f32 - win_percent_one, odds.two_wins, closing.two_wins, Winner::FighterTwo)
+            } else {
+                continue;
+            };
+
+        return Some(SimulatedBet {
+            placed_at: *taken_at,
+            probability: win_percent / 100f32,
+            odds,
+            closing_odds,
+            won: match bout.winner {
+                Winner::NoContest => None,
+                winner => Some(winner == backed),
+            },
+        });
+    }
+    None
+}
+
+// `stake` is given the bet and current bankroll, and says how much to put on it
+fn simulate_staking<F: Fn(&SimulatedBet, f32) -> f32>(bets: &[SimulatedBet], stake: F) -> StakingReport {
+    let mut report = StakingReport::default();
+    let mut bankroll = STARTING_BANKROLL;
+    let mut peak = bankroll;
+
+    for bet in bets {
+        let won = match bet.won {
+            Some(won) => won,
+            None => continue, // Void, stake returned
+        };
+        let stake = stake(bet, bankroll).max(0f32).min(bankroll);
+        if stake <= 0f32 { continue; }
+
+        report.bets += 1;
+        report.staked += stake;
+        let profit = if won {
+            report.wins += 1;
+            stake * (bet.odds.as_decimal() - 1f32)
+        } else {
+            -stake
+        };
+        report.profit += profit;
+        bankroll += profit;
+        peak = peak.max(bankroll);
+        report.max_drawdown = report.max_drawdown.max(peak - bankroll);
+    }
+    report
+}
+
+// Fraction of the bankroll the Kelly criterion says to stake, given our probability and decimal odds
+fn kelly_stake(probability: f32, decimal_odds: f32) -> f32 {
+    let b = decimal_odds - 1f32;
+    if b <= 0f32 { return 0f32; }
+    ((b * probability - (1f32 - probability)) / b).max(0f32)
+}
+
+// Closing line value, how much better the price we got was than the final price, as a percentage
+fn average_clv(bets: &[SimulatedBet]) -> f32 {
+    if bets.is_empty() { return 0f32; }
+    let total: f32 = bets.iter()
+        .map(|bet| bet.odds.as_decimal() / bet.closing_odds.as_decimal() - 1f32)
+        .sum();
+    100f32 * total / bets.len() as f32
+}
+
+// How well calibrated our model and Betfair's closing prices are at picking fighter one, lower is better
+fn print_brier_scores(bouts: &[HistoricalBout]) {
+    let mut model_total = 0f32;
+    let mut market_total = 0f32;
+    let mut count = 0;
+
+    for bout in bouts {
+        let outcome = match bout.winner {
+            Winner::FighterOne => 1f32,
+            Winner::FighterTwo | Winner::Draw => 0f32,
+            Winner::NoContest => continue,
+        };
+        let closing = match bout.snapshots.last() {
+            Some((_, closing)) => closing,
+            None => continue,
+        };
+        let model = match bout.model.last() {
+            Some((_, win_percent_one)) => win_percent_one / 100f32,
+            None => continue,
+        };
+        // Take the bookie's margin out so the market's probabilities add up to 1
+        let overround = closing.one_wins.as_percent() + closing.draw.as_percent() + closing.two_wins.as_percent();
+        let market = closing.one_wins.as_percent() / overround;
+
+        model_total += (model - outcome).powi(2);
+        market_total += (market - outcome).powi(2);
+        count += 1;
+    }
+
+    if count > 0 {
+        println!("Brier score over {} bout(s): BoxRec model {:.4}, Betfair closing prices {:.4}\n",
+                 count,
+                 model_total / count as f32,
+                 market_total / count as f32);
+    }
+}
@@ -0,0 +1,478 @@
+use std::error::Error;
+use std::fs;
+
+use futures::future::{FutureExt, LocalBoxFuture};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::odds::{Bout, BoutOdds, ExchangeOdds, Odds, OddsSource, RunnerPrices};
+
+// What the exchange goes by as an odds source
+pub const SOURCE_NAME: &str = "betfair_exchange";
+const LOGIN_URL: &str = "https://identitysso.betfair.com/api/login";
+const BETTING_URL: &str = "https://api.betfair.com/exchange/betting/rest/v1.0";
+// Followed by the market ID, for people to bet on the market
+const MARKET_URL: &str = "https://www.betfair.com/exchange/plus/boxing/market";
+// Betfair's ID for boxing
+const BOXING_EVENT_TYPE: &str = "6";
+// What the draw's runner is called, anything else is a fighter (who could well be called Drawson)
+const DRAW_RUNNER_NAMES: [&str; 2] = ["The Draw", "Draw"];
+// listMarketBook refuses requests that are too heavy, and best offers cost 5 of the 200 points per market
+const MARKETS_PER_BOOK_REQUEST: usize = 40;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExchangeConfig {
+    #[serde(default)]
+    pub app_key: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    // Answers requests from saved responses (e.g. listMarketBook.json) in this folder instead of Betfair
+    pub replay_dir: Option<String>,
+}
+
+// How requests get to the Exchange API, so saved responses can stand in for Betfair when offline
+pub trait ExchangeTransport: Send {
+    // Sends the JSON body to an operation of the betting API (e.g. listMarketCatalogue), returning the JSON response
+    fn call<'a>(&'a self, operation: &'a str, body: Value) -> LocalBoxFuture<'a, Result<Value, Box<dyn Error>>>;
+}
+
+// The real thing, logging in the first time it's needed (and again if the session expires)
+pub struct HttpTransport {
+    reqwest_client: Client,
+    app_key: String,
+    username: String,
+    password: String,
+    session_token: Mutex<Option<String>>,
+}
+
+impl HttpTransport {
+    pub fn new(config: &ExchangeConfig) -> Result<HttpTransport, Box<dyn Error>> {
+        if config.app_key.is_empty() || config.username.is_empty() || config.password.is_empty() {
+            return Err("betfair_exchange needs an app_key, username and password".into());
+        }
+        Ok(HttpTransport {
+            reqwest_client: Client::builder().build()?,
+            app_key: config.app_key.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+            session_token: Mutex::new(None),
+        })
+    }
+
+    async fn login(&self) -> Result<String, Box<dyn Error>> {
+        let response: LoginResponse = self.reqwest_client
+            .post(LOGIN_URL)
+            .header("X-Application", &self.app_key)
+            .header("Accept", "application/json")
+            .form(&[("username", &self.username), ("password", &self.password)])
+            .send()
+            .await?
+            .json()
+            .await?;
+        match response.token {
+            Some(token) if response.status == "SUCCESS" => Ok(token),
+            _ => Err(format!("Failed to log in to the Betfair Exchange ({})", response.error.unwrap_or(response.status)).into()),
+        }
+    }
+
+    async fn send(&self, operation: &str, body: &Value) -> Result<Value, Box<dyn Error>> {
+        let mut session_token = self.session_token.lock().await;
+        // Only retried once, after logging in again, as anything else won't be fixed by trying again
+        for attempt in 0..2 {
+            let token = match &*session_token {
+                Some(token) => token.clone(),
+                None => {
+                    let token = self.login().await?;
+                    *session_token = Some(token.clone());
+                    token
+                },
+            };
+            let response = self.reqwest_client
+                .post(&format!("{}/{}/", BETTING_URL, operation))
+                .header("X-Application", &self.app_key)
+                .header("X-Authentication", token)
+                .json(body)
+                .send()
+                .await?;
+            let status = response.status();
+            let response: Value = response.json().await?;
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            // Errors come back as {"detail": {"APINGException": {"errorCode": ...}}}
+            let error_code = response.pointer("/detail/APINGException/errorCode")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error")
+                .to_string();
+            if error_code == "INVALID_SESSION_INFORMATION" && attempt == 0 {
+                *session_token = None;
+                continue;
+            }
+            return Err(format!("Betfair Exchange {} failed ({}, {})", operation, status, error_code).into());
+        }
+        Err(format!("Betfair Exchange {} failed, couldn't stay logged in", operation).into())
+    }
+}
+
+impl ExchangeTransport for HttpTransport {
+    fn call<'a>(&'a self, operation: &'a str, body: Value) -> LocalBoxFuture<'a, Result<Value, Box<dyn Error>>> {
+        async move { self.send(operation, &body).await }.boxed_local()
+    }
+}
+
+// Reads each operation's response from <dir>/<operation>.json, whatever was asked for
+pub struct ReplayTransport {
+    dir: String,
+}
+
+impl ReplayTransport {
+    pub fn new(dir: &str) -> ReplayTransport {
+        ReplayTransport { dir: dir.to_string() }
+    }
+}
+
+impl ExchangeTransport for ReplayTransport {
+    fn call<'a>(&'a self, operation: &'a str, _body: Value) -> LocalBoxFuture<'a, Result<Value, Box<dyn Error>>> {
+        async move {
+            let path = format!("{}/{}.json", self.dir, operation);
+            let contents = fs::read_to_string(&path)
+                .map_err(|err| format!("Couldn't read saved response {} (Error: {})", path, err))?;
+            Ok(serde_json::from_str(&contents)?)
+        }.boxed_local()
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    token: Option<String>,
+    status: String,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MarketCatalogue {
+    market_id: String,
+    runners: Vec<RunnerCatalog>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RunnerCatalog {
+    selection_id: u64,
+    runner_name: String,
+    sort_priority: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MarketBook {
+    market_id: String,
+    runners: Vec<Runner>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Runner {
+    selection_id: u64,
+    #[serde(default)]
+    total_matched: f32,
+    #[serde(default)]
+    ex: AvailablePrices,
+}
+
+// Best first, so the first of each is what we'd get right now
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct AvailablePrices {
+    #[serde(default)]
+    available_to_back: Vec<PriceSize>,
+    #[serde(default)]
+    available_to_lay: Vec<PriceSize>,
+}
+
+#[derive(Deserialize)]
+struct PriceSize {
+    price: f32,
+}
+
+pub struct ExchangeAPI {
+    transport: Box<dyn ExchangeTransport>,
+}
+
+impl ExchangeAPI {
+    pub fn new(config: &ExchangeConfig) -> Result<ExchangeAPI, Box<dyn Error>> {
+        let transport: Box<dyn ExchangeTransport> = match &config.replay_dir {
+            Some(dir) => Box::new(ReplayTransport::new(dir)),
+            None => Box::new(HttpTransport::new(config)?),
+        };
+        Ok(ExchangeAPI::with_transport(transport))
+    }
+
+    pub fn with_transport(transport: Box<dyn ExchangeTransport>) -> ExchangeAPI {
+        ExchangeAPI { transport }
+    }
+
+    // Every boxing match odds market, priced at the best back price for each runner
+    pub async fn get_listed_bouts(&self) -> Result<Vec<Bout>, Box<dyn Error>> {
+        let catalogue: Vec<MarketCatalogue> = serde_json::from_value(self.transport.call("listMarketCatalogue", json!({
+            "filter": {
+                "eventTypeIds": [BOXING_EVENT_TYPE],
+                "marketTypeCodes": ["MATCH_ODDS"],
+            },
+            "marketProjection": ["RUNNER_DESCRIPTION"],
+            "maxResults": 200,
+        })).await?)?;
+
+        let mut books = Vec::new();
+        for markets in catalogue.chunks(MARKETS_PER_BOOK_REQUEST) {
+            let chunk: Vec<MarketBook> = serde_json::from_value(self.transport.call("listMarketBook", json!({
+                "marketIds": markets.iter().map(|market| &market.market_id).collect::<Vec<_>>(),
+                "priceProjection": {
+                    "priceData": ["EX_BEST_OFFERS"],
+                    "exBestOffersOverrides": { "bestPricesDepth": 1 },
+                },
+            })).await?)?;
+            books.extend(chunk);
+        }
+
+        Ok(catalogue.iter()
+            .filter_map(|market| {
+                let book = books.iter().find(|book| book.market_id == market.market_id)?;
+                match to_bout(market, book) {
+                    Ok(bout) => Some(bout),
+                    Err(err) => {
+                        errln!("Failed to read exchange market {} (Error: {})", market.market_id, err);
+                        None
+                    },
+                }
+            })
+            .collect())
+    }
+}
+
+impl OddsSource for ExchangeAPI {
+    fn name(&self) -> &str {
+        SOURCE_NAME
+    }
+
+    fn is_betfair(&self) -> bool {
+        true
+    }
+
+    fn get_listed_bouts(&self) -> LocalBoxFuture<'_, Result<Vec<Bout>, Box<dyn Error>>> {
+        ExchangeAPI::get_listed_bouts(self).boxed_local()
+    }
+}
+
+fn to_bout(market: &MarketCatalogue, book: &MarketBook) -> Result<Bout, Box<dyn Error>> {
+    let mut runners: Vec<&RunnerCatalog> = market.runners.iter().collect();
+    runners.sort_by_key(|runner| runner.sort_priority);
+    let (draws, fighters): (Vec<&RunnerCatalog>, Vec<&RunnerCatalog>) = runners.into_iter()
+        .partition(|runner| DRAW_RUNNER_NAMES.contains(&runner.runner_name.as_str()));
+    let (fighter_one, fighter_two, draw) = match (fighters.as_slice(), draws.as_slice()) {
+        ([one, two], [draw]) => (*one, *two, *draw),
+        _ => return Err("Expected two fighters and the draw".into()),
+    };
+
+    let prices = |runner: &RunnerCatalog| -> Result<RunnerPrices, Box<dyn Error>> {
+        let book = book.runners.iter()
+            .find(|book| book.selection_id == runner.selection_id)
+            .ok_or_else(|| format!("No prices for {}", runner.runner_name))?;
+        Ok(RunnerPrices {
+            back: match book.ex.available_to_back.first() {
+                Some(offer) => Some(Odds::from_decimal(offer.price)?),
+                None => None,
+            },
+            lay: match book.ex.available_to_lay.first() {
+                Some(offer) => Some(Odds::from_decimal(offer.price)?),
+                None => None,
+            },
+            matched: book.total_matched,
+        })
+    };
+    let exchange = ExchangeOdds {
+        one: prices(fighter_one)?,
+        draw: prices(draw)?,
+        two: prices(fighter_two)?,
+    };
+    let back = |prices: &RunnerPrices, runner: &RunnerCatalog| {
+        prices.back.ok_or_else(|| format!("No back price for {}", runner.runner_name))
+    };
+
+    Ok(Bout {
+        fighter_one: fighter_one.runner_name.clone(),
+        fighter_two: fighter_two.runner_name.clone(),
+        odds: BoutOdds {
+            one_wins: back(&exchange.one, fighter_one)?,
+            draw: back(&exchange.draw, draw)?,
+            two_wins: back(&exchange.two, fighter_two)?,
+        },
+        exchange: Some(exchange),
+        sources: None,
+        market_link: Some(format!("{}/{}", MARKET_URL, market.market_id)),
+        betfair_odds: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    // Answers with canned markets, only giving books for the markets asked for, like Betfair does
+    struct CannedTransport {
+        catalogue: Value,
+        books: Vec<Value>,
+        // How many markets each listMarketBook request asked for
+        book_requests: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl ExchangeTransport for CannedTransport {
+        fn call<'a>(&'a self, operation: &'a str, body: Value) -> LocalBoxFuture<'a, Result<Value, Box<dyn Error>>> {
+            async move {
+                match operation {
+                    "listMarketCatalogue" => Ok(self.catalogue.clone()),
+                    "listMarketBook" => {
+                        let ids = body["marketIds"].as_array().unwrap();
+                        self.book_requests.lock().unwrap().push(ids.len());
+                        Ok(Value::Array(self.books.iter().filter(|book| ids.contains(&book["marketId"])).cloned().collect()))
+                    },
+                    _ => Err(format!("Unexpected operation {}", operation).into()),
+                }
+            }.boxed_local()
+        }
+    }
+
+    // The bouts listed from the canned markets, and the size of each listMarketBook request
+    fn listed_bouts(catalogue: Vec<Value>, books: Vec<Value>) -> (Vec<Bout>, Vec<usize>) {
+        let book_requests = Arc::new(Mutex::new(Vec::new()));
+        let api = ExchangeAPI::with_transport(Box::new(CannedTransport {
+            catalogue: Value::Array(catalogue),
+            books,
+            book_requests: Arc::clone(&book_requests),
+        }));
+        let bouts = block_on(api.get_listed_bouts()).unwrap();
+        let book_requests = book_requests.lock().unwrap().clone();
+        (bouts, book_requests)
+    }
+
+    // (name, sort priority) for each runner, whose selection IDs count up from 1
+    fn market(id: &str, runners: &[(&str, u32)]) -> Value {
+        json!({
+            "marketId": id,
+            "runners": runners.iter().enumerate()
+                .map(|(i, (name, priority))| json!({ "selectionId": i + 1, "runnerName": name, "sortPriority": priority }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    // Best (back, lay) prices for each runner, in the same order as they're given to market()
+    fn book(id: &str, prices: &[(Option<f32>, Option<f32>)]) -> Value {
+        let offers = |price: &Option<f32>| price.iter().map(|price| json!({ "price": price, "size": 10 })).collect::<Vec<_>>();
+        json!({
+            "marketId": id,
+            "runners": prices.iter().enumerate()
+                .map(|(i, (back, lay))| json!({
+                    "selectionId": i + 1,
+                    "totalMatched": 100,
+                    "ex": { "availableToBack": offers(back), "availableToLay": offers(lay) },
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    fn decimal(price: f32) -> Odds {
+        Odds::from_decimal(price).unwrap()
+    }
+
+    // For a market listed fighter one, fighter two, then the draw
+    const PRICES: [(Option<f32>, Option<f32>); 3] = [(Some(2.0), Some(2.1)), (Some(1.8), Some(1.9)), (Some(21.0), None)];
+
+    #[test]
+    fn fighters_are_ordered_by_sort_priority() {
+        let (bouts, _) = listed_bouts(
+            vec![market("1.1", &[("Bob Baker", 2), ("The Draw", 3), ("Alan Able", 1)])],
+            vec![book("1.1", &[(Some(3.0), None), (Some(21.0), None), (Some(1.5), Some(1.52))])],
+        );
+
+        assert_eq!(bouts.len(), 1);
+        let bout = &bouts[0];
+        assert_eq!((bout.fighter_one.as_str(), bout.fighter_two.as_str()), ("Alan Able", "Bob Baker"));
+        assert_eq!(bout.odds.one_wins, decimal(1.5));
+        assert_eq!(bout.odds.draw, decimal(21.0));
+        assert_eq!(bout.odds.two_wins, decimal(3.0));
+        let exchange = bout.exchange.unwrap();
+        assert_eq!(exchange.one.lay, Some(decimal(1.52)));
+        assert_eq!(exchange.two.lay, None);
+    }
+
+    #[test]
+    fn draw_is_matched_by_name_exactly() {
+        let (bouts, _) = listed_bouts(
+            vec![market("1.1", &[("Andy Drawson", 1), ("Bob Baker", 2), ("Draw", 3)])],
+            vec![book("1.1", &PRICES)],
+        );
+
+        assert_eq!(bouts.len(), 1);
+        assert_eq!((bouts[0].fighter_one.as_str(), bouts[0].fighter_two.as_str()), ("Andy Drawson", "Bob Baker"));
+        assert_eq!(bouts[0].odds.draw, decimal(21.0));
+    }
+
+    #[test]
+    fn market_missing_a_back_price_is_skipped() {
+        let (bouts, _) = listed_bouts(
+            vec![
+                market("1.1", &[("Alan Able", 1), ("Bob Baker", 2), ("The Draw", 3)]),
+                market("1.2", &[("Carl Cole", 1), ("Dan Dunn", 2), ("The Draw", 3)]),
+            ],
+            vec![
+                book("1.1", &PRICES),
+                book("1.2", &[(Some(2.0), Some(2.1)), (None, Some(1.9)), (Some(21.0), None)]),
+            ],
+        );
+
+        assert_eq!(bouts.len(), 1);
+        assert_eq!(bouts[0].fighter_one, "Alan Able");
+    }
+
+    #[test]
+    fn market_without_a_book_is_skipped() {
+        let (bouts, _) = listed_bouts(
+            vec![
+                market("1.1", &[("Alan Able", 1), ("Bob Baker", 2), ("The Draw", 3)]),
+                market("1.2", &[("Carl Cole", 1), ("Dan Dunn", 2), ("The Draw", 3)]),
+            ],
+            vec![book("1.2", &PRICES)],
+        );
+
+        assert_eq!(bouts.len(), 1);
+        assert_eq!(bouts[0].fighter_one, "Carl Cole");
+        assert_eq!(bouts[0].market_link.as_deref(), Some("https://www.betfair.com/exchange/plus/boxing/market/1.2"));
+    }
+
+    #[test]
+    fn books_are_requested_in_chunks() {
+        let count = MARKETS_PER_BOOK_REQUEST + 5;
+        let names: Vec<(String, String)> = (0..count).map(|i| (format!("Fighter {}a", i), format!("Fighter {}b", i))).collect();
+        let (bouts, book_requests) = listed_bouts(
+            names.iter().enumerate()
+                .map(|(i, (one, two))| market(&format!("1.{}", i), &[(one, 1), (two, 2), ("The Draw", 3)]))
+                .collect(),
+            (0..count).map(|i| book(&format!("1.{}", i), &PRICES)).collect(),
+        );
+
+        assert_eq!(book_requests, vec![MARKETS_PER_BOOK_REQUEST, 5]);
+        assert_eq!(bouts.len(), count);
+        assert_eq!(bouts.last().unwrap().fighter_one, format!("Fighter {}a", count - 1));
+    }
+}
@@ -0,0 +1,138 @@
+use std::error::Error;
+
+use futures::future::{FutureExt, LocalBoxFuture};
+use reqwest::Client;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::odds::{Bout, BoutOdds, Odds, OddsSource};
+
+pub mod exchange;
+
+// What the Sportsbook goes by as an odds source
+pub const SOURCE_NAME: &str = "betfair_sportsbook";
+
+// Where all the boxing markets are listed
+pub const BETFAIR_BOXING_URL: &str = "https://www.betfair.com/sport/boxing";
+
+// Where Betfair's prices are taken from, when odds_sources isn't set
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BetfairMarket {
+    // The Sportsbook page, whose prices have Betfair's margin built in
+    Sportsbook,
+    // The Exchange API, with back and lay prices from other punters
+    Exchange,
+}
+
+// The Sportsbook page, scraped without logging in
+pub struct BetfairSportsbook {
+    reqwest_client: Client,
+}
+
+impl BetfairSportsbook {
+    pub fn new() -> Result<BetfairSportsbook, Box<dyn Error>> {
+        // Async client, no cookies
+        Ok(BetfairSportsbook {
+            reqwest_client:
+                Client::builder().build()?,
+        })
+    }
+
+    pub async fn get_listed_bouts(&self) -> Result<Vec<Bout>, Box<dyn Error>> {
+        let page = Html::parse_document(
+            &self.reqwest_client
+                .get(BETFAIR_BOXING_URL)
+                .send()
+                .await?
+                .text()
+                .await?
+        );
+
+        let bout_selector = Selector::parse(".com-coupon-line-new-layout.avb-table.quarter-template.avb-row").unwrap();
+
+        Ok(
+            page.select(&bout_selector)
+                .filter_map(|er| -> Option<Bout> {
+                    let odds = get_bout_odds(&er);
+                    match odds {
+                        Ok(odds) => {
+                            match get_bout_names(&er) {
+                                Ok((fighter_one, fighter_two)) => Some(Bout {
+                                    fighter_one,
+                                    fighter_two,
+                                    odds,
+                                    exchange: None,
+                                    sources: None,
+                                    market_link: get_bout_link(&er),
+                                    betfair_odds: None,
+                                }),
+                                Err(err) => {
+                                    errln!("Failed to get names for a bout (Error: {})", err);
+                                    None
+                                }
+                            }
+
+                        },
+                        Err(err) => {
+                            errln!("Failed to get odds for a bout (Error: {})", err);
+                            None
+                        },
+                    }
+                })
+                .collect()
+        )
+    }
+}
+
+impl OddsSource for BetfairSportsbook {
+    fn name(&self) -> &str {
+        SOURCE_NAME
+    }
+
+    fn is_betfair(&self) -> bool {
+        true
+    }
+
+    fn get_listed_bouts(&self) -> LocalBoxFuture<'_, Result<Vec<Bout>, Box<dyn Error>>> {
+        BetfairSportsbook::get_listed_bouts(self).boxed_local()
+    }
+}
+
+fn get_bout_names(fragment: &ElementRef) -> Result<(String, String), Box<dyn Error>> {
+    let name_selector = Selector::parse(".team-name").unwrap();
+    let mut names = fragment.select(&name_selector)
+        .map(|er2| -> String {
+            String::from(
+                er2.inner_html().trim()
+            )
+        });
+
+    Ok((
+        names.next().ok_or("First fighter's name not found")?,
+        names.next().ok_or("Second fighter's name not found")?,
+    ))
+}
+
+// The bout's own page, which the row links to from the fighters' names
+fn get_bout_link(fragment: &ElementRef) -> Option<String> {
+    let link_selector = Selector::parse("a[href^=\"/sport/boxing/\"]").unwrap();
+    fragment.select(&link_selector)
+        .next()
+        .and_then(|er| er.value().attr("href"))
+        .map(|href| format!("https://www.betfair.com{}", href))
+}
+
+fn get_bout_odds(fragment: &ElementRef) -> Result<BoutOdds, Box<dyn Error>> {
+    let odds_button_selector = Selector::parse(".com-bet-button").unwrap();
+    let mut raw_fracs = fragment.select(&odds_button_selector)
+        .map(|er| { er.inner_html() })
+        //.map(|s| { println!("{}", s); s })
+        .map(Odds::from_mangled_string);
+
+    Ok(BoutOdds {
+        one_wins: raw_fracs.next().unwrap()?,
+        draw:     raw_fracs.next().unwrap()?,
+        two_wins: raw_fracs.next().unwrap()?,
+    })
+}
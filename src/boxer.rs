@@ -5,10 +5,11 @@ use regex::Regex;
 use scraper::Selector;
 use serde::{Deserialize, Serialize};
 
-use crate::boxrec::BoxRecAPI;
+use crate::boxrec::{BoxRecAPI, Event};
+use crate::storage::ModelOutput;
 
 // If both boxers in a matchup have a score below this, a warning will be added
-const SCORE_WARNING: f32 = 2f32;
+pub const SCORE_WARNING: f32 = 2f32;
 
 pub struct Matchup<'a> {
     pub fighter_one: &'a Boxer,
@@ -16,6 +17,8 @@ pub struct Matchup<'a> {
     pub win_percent_one: f32,
     pub win_percent_two: f32,
     pub warning: bool,
+    // BoxRec's scores for fighter one and two, if we still have them
+    pub scores: Option<(f32, f32)>,
 }
 
 impl<'a> Matchup<'a> {
@@ -27,6 +30,19 @@ impl<'a> Matchup<'a> {
             win_percent_one,
             win_percent_two: 100f32 - win_percent_one,
             warning: fighter_one_score + fighter_two_score < 2f32 * SCORE_WARNING,
+            scores: Some((fighter_one_score, fighter_two_score)),
+        }
+    }
+
+    // Rebuilds a matchup from a previously stored model output
+    pub fn from_output(fighter_one: &'a Boxer, fighter_two: &'a Boxer, output: &ModelOutput) -> Matchup<'a> {
+        Matchup {
+            fighter_one,
+            fighter_two,
+            win_percent_one: output.win_percent_one,
+            win_percent_two: 100f32 - output.win_percent_one,
+            warning: output.warning,
+            scores: output.scores,
         }
     }
 }
@@ -39,32 +55,36 @@ pub struct Boxer {
 }
 
 impl Boxer {
-    pub fn new_by_name(api: &mut BoxRecAPI, name: &str) -> Option<Boxer> {
-        let (forename, surname) = match split_name(&name) {
+    pub fn new(id: u32, forename: String, surname: String) -> Boxer {
+        Boxer { id, forename, surname }
+    }
+
+    pub async fn new_by_name(api: &BoxRecAPI, name: &str) -> Option<Boxer> {
+        let (forename, surname) = match split_name(name) {
             Ok(tup) => tup,
             Err(err) => {
-                eprintln!("{}", err);
+                errln!("{}", err);
                 return None;
             },
         };
-        match api.boxer_search(&forename, &surname, false) {
+        match api.boxer_search(&forename, &surname, false).await {
             Ok(id) => Some(Boxer {
                 id,
                 forename,
                 surname,
             }),
             Err(err) => {
-                eprintln!("Failed to get boxer \"{}\" (Error: {})", name, err);
+                errln!("Failed to get boxer \"{}\" (Error: {})", name, err);
                 None
             },
         }
     }
 
-    pub fn new_by_id(api: &mut BoxRecAPI, id: u32) -> Option<Boxer> {
-        let page = match api.get_boxer_page_by_id(&id) {
+    pub async fn new_by_id(api: &BoxRecAPI, id: u32) -> Option<Boxer> {
+        let page = match api.get_boxer_page_by_id(&id).await {
             Ok(page) => page,
             Err(err) => {
-                eprintln!("Unable to find boxer {} (Error: {})", id, err);
+                errln!("Unable to find boxer {} (Error: {})", id, err);
                 return None;
             },
         };
@@ -79,16 +99,20 @@ impl Boxer {
             // The name is always in the form "BoxRec: Joe Bloggs"
             .find(|s| s.starts_with("BoxRec: "))
         { // Match the Option result
-            Some(name) => {
-                let (forename, surname) = split_name(&name[8..]).unwrap();
-                Some(Boxer {
+            // Some boxers only go by one name, which we can't split
+            Some(name) => match split_name(&name[8..]) {
+                Ok((forename, surname)) => Some(Boxer {
                     id,
                     forename,
                     surname,
-                })
+                }),
+                Err(err) => {
+                    errln!("Unable to use {}'s name (Error: {})", id, err);
+                    None
+                },
             },
             None => {
-                eprintln!("Unable to find name in {}'s page", id);
+                errln!("Unable to find name in {}'s page", id);
                 None
             },
         }
@@ -96,10 +120,14 @@ impl Boxer {
 
     pub fn get_name(&self) -> String { format!("{} {}", self.forename, self.surname) }
 
+    pub fn get_forename(&self) -> &str { &self.forename }
+
+    pub fn get_surname(&self) -> &str { &self.surname }
+
     pub fn get_id(&self) -> &u32 { &self.id }
 
-    pub fn get_bout_scores<'a>(&'a self, api: &mut BoxRecAPI, opponent: &'a Boxer) -> Result<Matchup<'a>, Box<dyn Error>> {
-        let bout_page = api.get_bout_page(&self.id, &opponent.get_name())?;
+    pub async fn get_bout_scores<'a>(&'a self, api: &BoxRecAPI, opponent: &'a Boxer) -> Result<(Event, Matchup<'a>), Box<dyn Error>> {
+        let (event, bout_page) = api.get_bout_page(&self.id, &opponent.get_name()).await?;
         let table_row_selector = Selector::parse(".responseLessDataTable").unwrap();
         // Floats below 1 are written as .086 (of course they are), hence the * for the first number
         let float_regex = Regex::new(r"[0-9]*\.[0-9]+").unwrap();
@@ -117,12 +145,12 @@ impl Boxer {
                             // And convert it to an option so the filter_map drops all the bad ones
                             .ok()
                     });
-                return Ok(Matchup::new(
+                return Ok((event, Matchup::new(
                     self,
                     scores.next().ok_or("Couldn't find first fighter's score")?,
                     opponent,
                     scores.next().ok_or("Couldn't find second fighter's score")?,
-                ));
+                )));
             }
         }
         Err("Couldn't find scores on bout page".into())
@@ -135,7 +163,7 @@ impl Display for Boxer {
     }
 }
 
-fn split_name(name: &str) -> Result<(String, String), String> {
+pub fn split_name(name: &str) -> Result<(String, String), String> {
     // Takes first word as forename and the rest as surname
     match name.find(" ") {
         Some(index) => Ok((
@@ -1,15 +1,18 @@
 use std::error::Error;
 use std::io::{self, Write};
-use std::ops::Sub;
-use std::thread::sleep;
-use std::time::{Duration, SystemTime};
+use std::sync::Arc;
 
+use chrono::NaiveDate;
 use regex::Regex;
-use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::{Client, RequestBuilder, StatusCode};
 use scraper::{Html, Selector};
+use tokio::sync::Mutex;
+use tokio::task;
 use trim_in_place::TrimInPlace;
 
 use crate::Config;
+use crate::pagecache::{PageCache, PageKind};
+use crate::ratelimit::RateLimiter;
 
 struct Login {
     username: String,
@@ -41,47 +44,143 @@ impl Login {
     }
 }
 
+// Asks which boxer was meant, when a search doesn't turn up an exact match
+// Returns the index of the choice, or an error if none of them will do
+pub trait BoxerPicker: Send {
+    fn pick(&mut self, query: &str, choices: &[(String, u32)]) -> Result<usize, Box<dyn Error>>;
+
+    // Waits for someone to complete BoxRec's reCAPTCHA, as whoever's picking boxers is who'd do it
+    // Returns an error if nobody can
+    fn captcha(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+// Lists the choices and asks for a number on the command line
+pub struct StdinPicker;
+
+impl BoxerPicker for StdinPicker {
+    fn pick(&mut self, query: &str, choices: &[(String, u32)]) -> Result<usize, Box<dyn Error>> {
+        println!("Exact match not found for '{}'. Please choose your fighter", query);
+        // If there's only one choice, pick the first item
+        if choices.len() == 1 {
+            return Ok(0);
+        }
+
+        // If there are multiple options, pretty print them and have the user choose
+        choices.iter()
+            .enumerate()
+            .for_each(|(n, (name, _))| {
+                println!("{}) {}", n + 1, name)
+            });
+        // Handle user input
+        loop {
+            match take_from_user("Pick a number: ")?.parse::<usize>() {
+                Ok(n) => {
+                    if n > 0 && n <= choices.len() {
+                        // Account for offset
+                        return Ok(n - 1);
+                    } else {
+                        println!("Please pick a valid number");
+                    }
+                },
+                Err(_) => println!("No, actually pick a number"),
+            }
+        }
+    }
+
+    fn captcha(&mut self) -> Result<(), Box<dyn Error>> {
+        println!("BoxRec is prompting for a reCAPTCHA\n\
+        Please visit the website and complete one under the login used \
+        by this program");
+        loop {
+            if take_from_user("Once done, type 'go': ")?.to_lowercase() == "go" {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// For when there's nobody around to ask (i.e. the daemon), so takes the only choice or gives up
+pub struct UnattendedPicker;
+
+impl BoxerPicker for UnattendedPicker {
+    fn pick(&mut self, query: &str, choices: &[(String, u32)]) -> Result<usize, Box<dyn Error>> {
+        match choices.len() {
+            1 => Ok(0),
+            _ => Err(format!("Exact match not found for '{}', and there's nobody to ask which of {} boxers was meant",
+                             query,
+                             choices.len()).into()),
+        }
+    }
+
+    fn captcha(&mut self) -> Result<(), Box<dyn Error>> {
+        Err("BoxRec is prompting for a reCAPTCHA, complete one under the login used by this program".into())
+    }
+}
+
+// Every method takes &self, so several lookups can be in flight at once
 pub struct BoxRecAPI {
     reqwest_client: Client,
-    request_delay: Duration,
-    last_sent: SystemTime,
+    // Held while waiting for a turn, so concurrent lookups still go out one at a time
+    limiter: Mutex<RateLimiter>,
+    page_cache: PageCache,
     login: Login,
+    // Shared with the blocking thread each question is asked on
+    picker: Arc<std::sync::Mutex<Box<dyn BoxerPicker>>>,
 }
 
 impl BoxRecAPI {
-    pub fn new(config: &Config) -> Result<BoxRecAPI, Box<dyn Error>> {
-        // Basic synchronous client with cookies enabled
-        let request_delay = Duration::from_millis(config.get_request_delay());
+    pub fn new(config: &Config, refresh: bool) -> Result<BoxRecAPI, Box<dyn Error>> {
+        // Async client with cookies enabled
         Ok(BoxRecAPI {
             reqwest_client:
                 Client::builder()
                     .cookie_store(true)
                     .build()?,
-            request_delay,
-            last_sent: SystemTime::now().sub(request_delay),
+            limiter: Mutex::new(RateLimiter::new(config)),
+            page_cache: PageCache::new(config, refresh),
             login: Login::get_from_config(config)?,
+            picker: Arc::new(std::sync::Mutex::new(Box::new(StdinPicker))),
         })
     }
 
-    // Returns a reference to self to allow for chaining
-    fn wait_if_needed(&mut self) -> &Self {
-        let time_since_request = SystemTime::now()
-            .duration_since(self.last_sent)
-            .unwrap();
-        // If we need to wait, do
-        if time_since_request.lt(&self.request_delay) {
-            // Calculate the time until we're okay to make the next request, and sleep for that time
-            sleep(self.request_delay.sub(time_since_request));
-        }
-        // Update last sent time
-        self.last_sent = SystemTime::now();
-        self
+    // Swaps how ambiguous boxer searches (and reCAPTCHAs) are resolved, i.e. for the TUI
+    pub fn set_picker(&mut self, picker: Box<dyn BoxerPicker>) {
+        self.picker = Arc::new(std::sync::Mutex::new(picker));
+    }
+
+    // Asks the picker on a blocking thread, as answering can take a while (i.e. reading stdin), and other lookups
+    // sharing the runtime shouldn't have to wait on it. Only one question is asked at a time
+    async fn ask<T, F>(&self, question: F) -> Result<T, Box<dyn Error>>
+        where T: Send + 'static,
+              F: FnOnce(&mut dyn BoxerPicker) -> Result<T, Box<dyn Error>> + Send + 'static {
+        let picker = Arc::clone(&self.picker);
+        let answer = task::spawn_blocking(move || {
+            let mut picker = picker.lock().map_err(|_| "Boxer picker panicked")?;
+            // Errors aren't Send, so they come back as strings
+            question(picker.as_mut()).map_err(|err| err.to_string())
+        }).await?;
+        Ok(answer?)
+    }
+
+    // True once today's request budget is spent, any further lookups should wait for the next run
+    pub async fn budget_exhausted(&self) -> bool {
+        self.limiter.lock().await.is_exhausted()
     }
 
-    pub fn login(&mut self) -> Result<(), Box<dyn Error>> {
-        println!("Sending login request");
+    // Logging in isn't charged to the daily budget, and is skipped once it's spent so the rest of the run
+    // (Betfair, arbitrages) carries on. The first lookup once there's budget again logs in then
+    pub async fn login(&self) -> Result<(), Box<dyn Error>> {
+        {
+            let mut limiter = self.limiter.lock().await;
+            if limiter.is_exhausted() {
+                errln!("Today's BoxRec request budget has been spent, not logging in");
+                return Ok(());
+            }
+            limiter.pace().await;
+        }
+        errln!("Sending login request");
 
-        let response = self.wait_if_needed().reqwest_client
+        let response = self.reqwest_client
             .post("https://boxrec.com/en/login")
             .form::<[(&str, &str); 4]>(&[
                 ("_username", &self.login.username),
@@ -89,49 +188,58 @@ impl BoxRecAPI {
                 ("_remember_me", "on"),
                 ("login[go]", ""),
             ])
-            .send()?;
+            .send()
+            .await?;
 
         // If login is successful, you are redirected to the home page instead of the login page
         if response.url().as_str() == "https://boxrec.com/en/login" {
             Err("Failed to login".into())
         } else {
-            println!("Logged in successfully");
+            errln!("Logged in successfully");
             Ok(())
         }
     }
 
-    fn try_request_and_unwrap(&mut self, req: &RequestBuilder) -> Result<String, Box<dyn Error>> {
+    async fn try_request_and_unwrap(&self, req: &RequestBuilder) -> Result<String, Box<dyn Error>> {
         loop {
-            self.wait_if_needed();
-            let response = req.try_clone().ok_or("Failed to clone request")?.send()?;
-            if response.url().as_str().contains("login") {
-                eprintln!("Logged out by BoxRec, attempting to login");
-                self.login()?;
+            self.limiter.lock().await.acquire().await?;
+            let response = req.try_clone().ok_or("Failed to clone request")?.send().await?;
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                // Back off and try again once the limiter lets us
+                self.limiter.lock().await.throttled();
+            } else if response.url().as_str().contains("login") {
+                errln!("Logged out by BoxRec, attempting to login");
+                self.login().await?;
             } else {
-                let text = response.text()?;
+                let text = response.text().await?;
                 if text.contains("Please complete the form below to continue...") {
-                    println!("BoxRec is prompting for a reCAPTCHA\n\
-                    Please visit the website and complete one under the login used \
-                    by this program");
-                    loop {
-                        if take_from_user("Once done, type 'go': ")?.to_lowercase() == "go" {
-                            break;
-                        }
-                    }
+                    self.limiter.lock().await.throttled();
+                    self.ask(|picker| picker.captcha()).await?;
                 } else {
+                    self.limiter.lock().await.succeeded();
                     return Ok(text);
                 }
             }
         }
     }
 
-    pub fn get_boxer_page_by_id(&mut self, id: &u32) -> Result<Html, Box<dyn Error>> {
-        let url = format!("https://boxrec.com/en/proboxer/{}", id);
-        let response = self.try_request_and_unwrap(&self.reqwest_client.get(&url))?;
+    // Checks the page cache before going to BoxRec, and caches whatever we download
+    async fn get_page(&self, url: &str, kind: PageKind) -> Result<String, Box<dyn Error>> {
+        if let Some(page) = self.page_cache.get(url, kind) {
+            return Ok(page);
+        }
+        let page = self.try_request_and_unwrap(&self.reqwest_client.get(url)).await?;
+        self.page_cache.put(url, &page);
+        Ok(page)
+    }
+
+    pub async fn get_boxer_page_by_id(&self, id: &u32) -> Result<Html, Box<dyn Error>> {
+        let response = self.get_page(&profile_url(id), PageKind::Profile).await?;
         Ok(Html::parse_document(&response))
     }
 
-    pub fn boxer_search(&mut self, forename: &str, surname: &str, active_only: bool) -> Result<u32, Box<dyn Error>> {
+    // Everyone BoxRec lists for the name, as (name, ID) in BoxRec's order, without asking the user anything
+    pub async fn search_boxers(&self, forename: &str, surname: &str, active_only: bool) -> Result<Vec<(String, u32)>, Box<dyn Error>> {
         // Step 1: perform request
         let forename = forename.to_lowercase();
         let surname = surname.to_lowercase();
@@ -141,85 +249,60 @@ impl BoxRecAPI {
             surname,
             if active_only { "a" } else { "" }
         );
-        let response = self.try_request_and_unwrap(&self.reqwest_client.get(&url))?;
+        let response = self.get_page(&url, PageKind::Search).await?;
 
         // Step 2: parse results
         let response = Html::parse_document(&response);
         let selector = Selector::parse("a.personLink").unwrap();
-        let mut results = response.select(&selector).peekable();
         let re = Regex::new(r"[0-9]{3,}").unwrap();
+        Ok(response.select(&selector)
+            // Find ID of boxer using regex search on the link
+            .filter_map(|er| {
+                let id = re.find(&er.html())?.as_str().parse::<u32>().ok()?;
+                Some((er.inner_html(), id))
+            })
+            .collect())
+    }
 
-        let target;
-        let search_in;
-        let choices;
+    pub async fn boxer_search(&self, forename: &str, surname: &str, active_only: bool) -> Result<u32, Box<dyn Error>> {
+        let choices = self.search_boxers(forename, surname, active_only).await?;
+        let query = format!("{} {}", forename, surname).to_lowercase();
 
-        if results.peek().is_none() {
+        let choice = if choices.is_empty() {
             // Error if there are no results
             return Err("No results".into());
-        } else if results.peek().unwrap().inner_html().to_lowercase() == format!("{} {}", forename, surname) {
+        } else if choices[0].0.to_lowercase() == query {
             // Exact match, accept
-            search_in = results.next()
-                .unwrap()
-                .html();
-            // Find ID of boxer using regex search
-            target = re.find(search_in.as_str()).unwrap().as_str();
+            0
         } else {
-            // No exact match, list results and have user pick
-            println!("Exact match not found for '{} {}'. Please choose your fighter",
-                forename,
-                surname);
-            // Grab the name (inner_html) and the whole page
-            choices = results.map(|er| (er.inner_html(), er.html()))
-                .collect::<Vec<_>>();
-            let choice: usize;
-            if choices.len() > 1 {
-                // If there are multiple options, pretty print them and have the user choose
-                choices.iter()
-                    .enumerate()
-                    .for_each(|(n, (name, _))| {
-                        println!("{}) {}", n + 1, name)
-                    });
-                // Handle user input
-                loop {
-                    print!("Pick a number: ");
-                    io::stdout().flush()?;
-                    let mut temp = String::new();
-                    io::stdin()
-                        .read_line(&mut temp)?;
-                    match temp.trim().parse::<usize>() {
-                        Ok(n) => {
-                            if n > 0 && n <= choices.len() {
-                                // Account for offset
-                                choice = n - 1;
-                                break;
-                            } else {
-                                println!("Please pick a valid number");
-                            }
-                        },
-                        Err(_) => println!("No, actually pick a number"),
-                    }
-                }
-            } else {
-                // If there's only one choice, pick the first item
-                choice = 0;
+            // No exact match, have the user pick
+            let listed = choices.clone();
+            let choice = self.ask(move |picker| picker.pick(&query, &listed)).await?;
+            if choice >= choices.len() {
+                return Err("Picked a boxer that wasn't listed".into());
             }
-            // End user input
-            // Find ID of boxer using regex search
-            target = re.find(
-                // Tuple index 1 to search the page contents
-                &choices.get(choice).unwrap().1
-            ).unwrap()
-                .as_str();
-        }
-        // Parse String ID of boxer to u32
-        let boxer_id = target.parse::<u32>().unwrap();
-        println!("Selected: {}", boxer_id);
+            choice
+        };
+        let boxer_id = choices[choice].1;
+        errln!("Selected: {}", boxer_id);
         Ok(boxer_id)
     }
 
+    // Every bout on a boxer's profile, upcoming ones included, most recent first (as BoxRec lists them)
+    pub async fn get_fight_history(&self, id: &u32) -> Result<Vec<Fight>, Box<dyn Error>> {
+        let page = self.get_boxer_page_by_id(id).await?;
+        Ok(parse_fight_history(&page))
+    }
+
+    // Like get_fight_history, but only from the page cache so it never costs a request
+    pub fn cached_fight_history(&self, id: &u32) -> Option<Vec<Fight>> {
+        let page = self.page_cache.peek(&profile_url(id), PageKind::Profile)?;
+        Some(parse_fight_history(&Html::parse_document(&page)))
+    }
+
     // TODO: maybe make args a bit more user friendly
-    pub fn get_bout_page(&mut self, id_1: &u32, name_2: &str) -> Result<Html, Box<dyn Error>> {
-        let boxer_1 = self.get_boxer_page_by_id(id_1)?;
+    pub async fn get_bout_page(&self, id_1: &u32, name_2: &str) -> Result<(Event, Html), Box<dyn Error>> {
+        let boxer_1 = self.get_boxer_page_by_id(id_1).await?;
         let name_2 = name_2.to_lowercase();
         let scheduled_bouts_selector = Selector::parse(".scheduleRow").unwrap();
 
@@ -234,22 +317,159 @@ impl BoxRecAPI {
         for upcoming_fight in scheduled_fights {
             let upcoming_fight = upcoming_fight.html();
             // Check if a URL is found first, this isn't guaranteed
-            match bout_link_regex.find(&upcoming_fight) {
+            if let Some(link) = bout_link_regex.find(&upcoming_fight) {
                 // If a URL is found, check that this entry is for the correct opponent
-                Some(link) => if upcoming_fight.to_lowercase().contains(&name_2) {
-                    println!("Found matching bout");
+                if upcoming_fight.to_lowercase().contains(&name_2) {
+                    errln!("Found matching bout");
                     // Once a matching bout has been found, download the page
                     let url = format!("https://boxrec.com{}", link.as_str());
-                    let bout_page = self.try_request_and_unwrap(&self.reqwest_client.get(&url))?;
+                    let bout_page = self.get_page(&url, PageKind::Event).await?;
+                    let event = Event {
+                        link: link.as_str().to_string(),
+                        date: find_event_date(&bout_page),
+                    };
                     // Pass onto the next stage
-                    return Ok(Html::parse_document(&bout_page));
-                },
-                None => {},
+                    return Ok((event, Html::parse_document(&bout_page)));
+                }
             }
         }
         // If nothing is found after going through all the scheduled entries, say we couldn't find any
         Err("Unable to find any bouts matching search criteria".into())
     }
+
+    // Looks for how a bout went on the first boxer's profile, None means it hasn't happened yet
+    pub async fn get_bout_settlement(&self, id_1: &u32, event: &Event) -> Result<Option<Settlement>, Box<dyn Error>> {
+        let boxer_1 = self.get_boxer_page_by_id(id_1).await?;
+        let row_selector = Selector::parse("tr").unwrap();
+        let result_selector = Selector::parse(".boutResult").unwrap();
+        let method_regex = Regex::new(r"\b(KO|TKO|RTD|UD|MD|SD|PTS|TD|DQ)\b").unwrap();
+
+        for row in boxer_1.select(&row_selector) {
+            let raw_html = row.html();
+            if !raw_html.contains(&event.link) { continue; }
+
+            if raw_html.to_lowercase().contains("cancelled") {
+                return Ok(Some(Settlement::Cancelled));
+            }
+            // Still listed as upcoming
+            if row.value().classes().any(|class| class == "scheduleRow") {
+                return Ok(None);
+            }
+
+            // Results are from the first boxer's point of view
+            let winner = match row.select(&result_selector)
+                .next()
+                .map(|er| er.text().collect::<String>().trim().to_uppercase())
+                .as_deref()
+            {
+                Some("W") => Winner::FighterOne,
+                Some("L") => Winner::FighterTwo,
+                Some("D") => Winner::Draw,
+                Some("NC") => Winner::NoContest,
+                _ => return Ok(None),
+            };
+            let text = row.text().collect::<String>();
+            let method = method_regex.find(&text).map(|m| m.as_str().to_string());
+            return Ok(Some(Settlement::Completed(BoutResult { winner, method })));
+        }
+        Ok(None)
+    }
+}
+
+// Where a bout is happening, found from its BoxRec bout page
+#[derive(Clone, Debug)]
+pub struct Event {
+    // e.g. /en/event/123456/1234567
+    pub link: String,
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Winner {
+    FighterOne,
+    FighterTwo,
+    Draw,
+    NoContest,
+}
+
+#[derive(Clone, Debug)]
+pub struct BoutResult {
+    pub winner: Winner,
+    // e.g. KO, TKO, UD
+    pub method: Option<String>,
+}
+
+// A line of a boxer's record, as shown on their profile
+#[derive(Clone, Debug)]
+pub struct Fight {
+    pub date: Option<NaiveDate>,
+    pub opponent: String,
+    // W, L, D or NC from the boxer's point of view, or Scheduled/Cancelled
+    pub result: String,
+    pub method: Option<String>,
+}
+
+pub enum Settlement {
+    Completed(BoutResult),
+    Cancelled,
+}
+
+// Every bout on a boxer's profile page, most recent first
+fn parse_fight_history(page: &Html) -> Vec<Fight> {
+    let row_selector = Selector::parse("tr").unwrap();
+    let opponent_selector = Selector::parse("a.personLink").unwrap();
+    let result_selector = Selector::parse(".boutResult").unwrap();
+    let event_link_regex = Regex::new(r"/en/event/[0-9]{6,}/[0-9]{7,}").unwrap();
+    let method_regex = Regex::new(r"\b(KO|TKO|RTD|UD|MD|SD|PTS|TD|DQ)\b").unwrap();
+
+    let mut fights = Vec::new();
+    for row in page.select(&row_selector) {
+        let raw_html = row.html();
+        // Only rows for bouts link to an event
+        if !event_link_regex.is_match(&raw_html) { continue; }
+
+        let text = row.text().collect::<String>();
+        fights.push(Fight {
+            date: find_date(&raw_html),
+            opponent: row.select(&opponent_selector)
+                .next()
+                .map(|er| er.text().collect::<String>().trim().to_string())
+                .unwrap_or_else(|| String::from("Unknown")),
+            result: if raw_html.to_lowercase().contains("cancelled") {
+                String::from("Cancelled")
+            } else if row.value().classes().any(|class| class == "scheduleRow") {
+                String::from("Scheduled")
+            } else {
+                row.select(&result_selector)
+                    .next()
+                    .map(|er| er.text().collect::<String>().trim().to_uppercase())
+                    .unwrap_or_default()
+            },
+            method: method_regex.find(&text).map(|m| m.as_str().to_string()),
+        });
+    }
+    fights
+}
+
+fn profile_url(id: &u32) -> String {
+    format!("https://boxrec.com/en/proboxer/{}", id)
+}
+
+// BoxRec writes dates as 2020-10-31, the first one in a profile row is the date of the bout
+fn find_date(row: &str) -> Option<NaiveDate> {
+    let date_regex = Regex::new(r"[0-9]{4}-[0-9]{2}-[0-9]{2}").unwrap();
+    let date = date_regex.find_iter(row)
+        .find_map(|m| NaiveDate::parse_from_str(m.as_str(), "%Y-%m-%d").ok());
+    date
+}
+
+// An event page has plenty of other dates on it (e.g. boxers' last fights), but only the event's own date
+// links to BoxRec's schedule for that day, e.g. /en/date?date=2020-10-31
+fn find_event_date(page: &str) -> Option<NaiveDate> {
+    let date_regex = Regex::new(r"/date\?date=([0-9]{4}-[0-9]{2}-[0-9]{2})").unwrap();
+    let date = date_regex.captures(page)
+        .and_then(|captures| NaiveDate::parse_from_str(&captures[1], "%Y-%m-%d").ok());
+    date
 }
 
 fn take_from_user(prompt: &str) -> Result<String, Box<dyn Error>> {
@@ -257,8 +477,10 @@ fn take_from_user(prompt: &str) -> Result<String, Box<dyn Error>> {
     print!("{}", prompt);
     // ensures the prompt is actually printed, as Rust usually only flushes on newline
     io::stdout().flush()?;
-    io::stdin()
-        .read_line(&mut input)?;
+    // Nothing left to read (i.e. stdin is /dev/null), so nobody's ever going to answer
+    if io::stdin().read_line(&mut input)? == 0 {
+        return Err("No answer, as stdin is closed".into());
+    }
     // I actually had to get a crate for this...
     input.trim_in_place();
     Ok(input)
@@ -0,0 +1,192 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+use std::process;
+
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::storage::now;
+
+// Writes to a temporary file next to the target then renames it over the top, so a crash part way
+// through leaves the old file intact rather than half of the new one
+pub fn atomic_write<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        path.file_name().and_then(|name| name.to_str()).unwrap_or("cache"),
+        process::id()
+    ));
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        // Belt and braces: make sure everything actually made it to disk before replacing the old file
+        let written = file.metadata()?.len();
+        if written != contents.len() as u64 {
+            return Err(io::Error::new(
+                ErrorKind::WriteZero,
+                format!("only wrote {} of {} bytes", written, contents.len()),
+            ));
+        }
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        // Don't leave the temporary file lying around, though there's nothing to be done if this fails too
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// Exclusive lock on the cache folder, held for as long as this is in scope
+// Uses an OS file lock, so it's released even if the program crashes
+pub struct CacheLock {
+    file: File,
+}
+
+impl CacheLock {
+    // Returns None if another run holds the lock and we've been told not to wait for it
+    pub fn acquire(cache_path: &str, wait: bool) -> Result<Option<CacheLock>, Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(format!("{}/.lock", cache_path))?;
+
+        if let Err(err) = file.try_lock_exclusive() {
+            if err.raw_os_error() != fs2::lock_contended_error().raw_os_error() {
+                return Err(err.into());
+            }
+            if !wait {
+                return Ok(None);
+            }
+            errln!("Another run is using the cache, waiting for it to finish");
+            file.lock_exclusive()?;
+        }
+
+        // Leave our PID in the file, to help work out who's holding it if anything gets stuck
+        file.set_len(0)?;
+        write!(file, "{}", process::id())?;
+        Ok(Some(CacheLock { file }))
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+// Makes sure the cache folder exists (and is actually a folder)
+pub fn create_cache_dir(cache_path: &str) -> Result<(), Box<dyn Error>> {
+    match fs::metadata(cache_path) {
+        // If Ok(), it exists
+        // If it's a file, get scared, otherwise, we have a folder!
+        Ok(md) => if md.is_file() {
+            return Err("Cache path points to an existing file".into());
+        },
+        Err(e) => match e.kind() {
+            // If the folder doesn't exist yet, try and make it
+            ErrorKind::NotFound => fs::create_dir_all(cache_path)?,
+            // If there's another error be spooked
+            _ => return Err(e.into()),
+        }
+    };
+    Ok(())
+}
+
+// On-disk envelope for anything we serialise ourselves, so the format can change without old caches
+// failing to parse
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    data: T,
+}
+
+// Takes serialised data from one version to the next
+pub type Migration = fn(Value) -> Result<Value, Box<dyn Error>>;
+
+// The latest version is the number of migrations there are, as version 0 is the unversioned format
+pub fn write_versioned<T: Serialize>(path: &str, migrations: &[Migration], data: &T) -> Result<(), Box<dyn Error>> {
+    let ser = serde_yaml::to_string(&Versioned {
+        version: migrations.len() as u32,
+        data,
+    })?;
+    atomic_write(path, ser.as_bytes())?;
+    Ok(())
+}
+
+// Reads a file written by write_versioned, migrating it up to the latest version as needed
+// Files without the envelope are from before versioning, so count as version 0
+// Returns None if the file doesn't exist, or if it can't be read, in which case it's quarantined
+// rather than taking the whole run down with it
+pub fn read_versioned<T: DeserializeOwned>(path: &str, migrations: &[Migration]) -> Option<T> {
+    let serialised = match fs::read_to_string(path) {
+        Ok(serialised) => serialised,
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                errln!("Failed to read {} (Error: {})", path, err);
+            }
+            return None;
+        },
+    };
+
+    match parse_versioned(&serialised, migrations) {
+        Ok(data) => Some(data),
+        Err(err) => {
+            errln!("Failed to parse {} (Error: {})", path, err);
+            quarantine(path);
+            None
+        },
+    }
+}
+
+fn parse_versioned<T: DeserializeOwned>(serialised: &str, migrations: &[Migration]) -> Result<T, Box<dyn Error>> {
+    let value: Value = serde_yaml::from_str(serialised)?;
+    let (version, mut data) = if is_envelope(&value) {
+        let envelope: Versioned<Value> = serde_yaml::from_value(value)?;
+        (envelope.version as usize, envelope.data)
+    } else {
+        (0, value)
+    };
+    if version > migrations.len() {
+        return Err(format!("written by a newer version of this program (version {}, expected at most {})",
+                           version,
+                           migrations.len()).into());
+    }
+
+    for migration in &migrations[version..] {
+        data = migration(data)?;
+    }
+    Ok(serde_yaml::from_value(data)?)
+}
+
+fn is_envelope(value: &Value) -> bool {
+    match value {
+        Value::Mapping(map) => map.len() == 2
+            && map.get(&Value::from("version")).is_some_and(Value::is_u64)
+            && map.contains_key(&Value::from("data")),
+        _ => false,
+    }
+}
+
+// Moves a cache file we can't make sense of out of the way, keeping it in case someone wants to
+// look at it (or fix it by hand)
+pub fn quarantine(path: &str) {
+    let path = Path::new(path);
+    let quarantine_dir = path.with_file_name("quarantine");
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("cache");
+    let destination = quarantine_dir.join(format!("{}.{}", file_name, now()));
+
+    let result = fs::create_dir_all(&quarantine_dir)
+        .and_then(|_| fs::rename(path, &destination));
+    match result {
+        Ok(_) => errln!("Moved {} to {} so it can be looked at later", path.display(), destination.display()),
+        Err(err) => errln!("Failed to quarantine {} (Error: {})", path.display(), err),
+    }
+}
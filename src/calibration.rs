@@ -0,0 +1,222 @@
+use std::error::Error;
+
+use crate::boxrec::Winner;
+use crate::cache::atomic_write;
+use crate::chart::{Chart, CHART_MARGIN, SQUARE_CHART_SIZE};
+use crate::storage::Storage;
+
+// One colour per model, wrapping round if there are more models than colours
+const MODEL_COLOURS: [&str; 4] = ["#1f77b4", "#d62728", "#2ca02c", "#9467bd"];
+// Keeps log loss finite when a model is 100% sure and wrong
+const MIN_PROBABILITY: f32 = 0.0001;
+
+struct Bin {
+    low: f32,
+    high: f32,
+    count: usize,
+    predicted_total: f32,
+    wins: usize,
+}
+
+impl Bin {
+    fn mean_predicted(&self) -> f32 {
+        self.predicted_total / self.count as f32
+    }
+
+    fn observed(&self) -> f32 {
+        self.wins as f32 / self.count as f32
+    }
+}
+
+struct ModelCalibration {
+    model: String,
+    bouts: usize,
+    bins: Vec<Bin>,
+    brier: f32,
+    log_loss: f32,
+}
+
+// Compares what each model predicted against what actually happened in completed bouts
+pub fn show_calibration(storage: &Storage,
+                        bin_count: usize,
+                        csv_path: Option<&str>,
+                        chart_path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    if bin_count == 0 {
+        return Err("Need at least 1 bin".into());
+    }
+
+    let completed = storage.completed_bouts()?;
+    let mut calibrations = Vec::new();
+    for model in storage.model_names()? {
+        // What the model last said about each bout before it happened, and how it went
+        let mut predictions = Vec::new();
+        for (id, winner) in &completed {
+            if *winner == Winner::NoContest {
+                continue;
+            }
+            if let Some((_, win_percent_one)) = storage.model_history(*id, &model)?.last() {
+                predictions.push((win_percent_one / 100f32, *winner));
+            }
+        }
+        if !predictions.is_empty() {
+            calibrations.push(calibrate(model, &predictions, bin_count));
+        }
+    }
+
+    if calibrations.is_empty() {
+        println!("No completed bouts with model outputs to check calibration against yet");
+        return Ok(());
+    }
+
+    for calibration in &calibrations {
+        println!("{} ({} bout(s)): Brier score {:.4}, log loss {:.4}",
+                 calibration.model,
+                 calibration.bouts,
+                 calibration.brier,
+                 calibration.log_loss);
+        println!("{:>11}  {:>5}  {:>9}  {:>8}", "Predicted", "Count", "Mean", "Observed");
+        for bin in calibration.bins.iter().filter(|bin| bin.count > 0) {
+            println!("{:>11}  {:>5}  {:>8.1}%  {:>7.1}%",
+                     format!("{:.0}-{:.0}%", 100f32 * bin.low, 100f32 * bin.high),
+                     bin.count,
+                     100f32 * bin.mean_predicted(),
+                     100f32 * bin.observed());
+        }
+        println!();
+    }
+
+    if let Some(path) = csv_path {
+        atomic_write(path, render_csv(&calibrations).as_bytes())?;
+        println!("Calibration table saved to {}", path);
+    }
+    if let Some(path) = chart_path {
+        atomic_write(path, render_reliability_diagram(&calibrations).as_bytes())?;
+        println!("Reliability diagram saved to {}", path);
+    }
+    Ok(())
+}
+
+// Each bout counts once per fighter, so 70% for one fighter and 30% for the other both get checked.
+// A draw is a loss for both of them, as neither won
+fn calibrate(model: String, predictions: &[(f32, Winner)], bin_count: usize) -> ModelCalibration {
+    let mut bins = (0..bin_count)
+        .map(|index| Bin {
+            low: index as f32 / bin_count as f32,
+            high: (index + 1) as f32 / bin_count as f32,
+            count: 0,
+            predicted_total: 0f32,
+            wins: 0,
+        })
+        .collect::<Vec<_>>();
+
+    let mut brier = 0f32;
+    let mut log_loss = 0f32;
+    for (probability_one, winner) in predictions {
+        let sides = [
+            (*probability_one, *winner == Winner::FighterOne),
+            (1f32 - probability_one, *winner == Winner::FighterTwo),
+        ];
+        for (probability, won) in &sides {
+            let index = ((probability * bin_count as f32) as usize).min(bin_count - 1);
+            let bin = &mut bins[index];
+            bin.count += 1;
+            bin.predicted_total += probability;
+            if *won { bin.wins += 1; }
+
+            let outcome = if *won { 1f32 } else { 0f32 };
+            let clamped = probability.clamp(MIN_PROBABILITY, 1f32 - MIN_PROBABILITY);
+            brier += (probability - outcome).powi(2);
+            log_loss -= if *won { clamped.ln() } else { (1f32 - clamped).ln() };
+        }
+    }
+
+    // Per fighter, which without draws comes out the same as just scoring fighter one
+    let fighters = 2f32 * predictions.len() as f32;
+    ModelCalibration {
+        model,
+        bouts: predictions.len(),
+        bins,
+        brier: brier / fighters,
+        log_loss: log_loss / fighters,
+    }
+}
+
+fn render_csv(calibrations: &[ModelCalibration]) -> String {
+    let mut csv = String::from("model,bin_low,bin_high,count,mean_predicted,observed\n");
+    for calibration in calibrations {
+        for bin in calibration.bins.iter().filter(|bin| bin.count > 0) {
+            csv.push_str(&format!("{},{:.2},{:.2},{},{:.4},{:.4}\n",
+                                  calibration.model,
+                                  bin.low,
+                                  bin.high,
+                                  bin.count,
+                                  bin.mean_predicted(),
+                                  bin.observed()));
+        }
+    }
+    csv
+}
+
+// Mean predicted probability against observed win rate for each bin, a perfectly calibrated model
+// sits on the diagonal
+fn render_reliability_diagram(calibrations: &[ModelCalibration]) -> String {
+    let mut chart = Chart::new(SQUARE_CHART_SIZE, SQUARE_CHART_SIZE, "Reliability diagram");
+    chart.push_str(&format!(
+        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#999999\" stroke-dasharray=\"6 4\"/>\n",
+        chart.x(0f32),
+        chart.y(0f32),
+        chart.x(1f32),
+        chart.y(1f32),
+    ));
+    chart.text(SQUARE_CHART_SIZE / 2f32, SQUARE_CHART_SIZE - 10f32, "middle", "Predicted");
+    chart.push_str(&format!(
+        "<text x=\"15\" y=\"{y}\" text-anchor=\"middle\" transform=\"rotate(-90 15 {y})\">Observed</text>\n",
+        y = SQUARE_CHART_SIZE / 2f32,
+    ));
+
+    // Gridlines every 25%, labelled along the bottom too
+    chart.percent_gridlines();
+    for percent in (0..=100).step_by(25) {
+        chart.text(chart.x(percent as f32 / 100f32), chart.y(0f32) + 18f32, "middle", &format!("{}%", percent));
+    }
+
+    for (index, calibration) in calibrations.iter().enumerate() {
+        let colour = MODEL_COLOURS[index % MODEL_COLOURS.len()];
+        let points = calibration.bins.iter()
+            .filter(|bin| bin.count > 0)
+            .map(|bin| (bin.mean_predicted(), bin.observed()))
+            .collect::<Vec<_>>();
+        chart.polyline(&points, colour, 2f32, false);
+        for (px, py) in &points {
+            chart.push_str(&format!("<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"{}\"/>\n", chart.x(*px), chart.y(*py), colour));
+        }
+
+        // Legend
+        chart.legend_entry(chart.x(0f32) + 10f32, CHART_MARGIN + 5f32 + index as f32 * 18f32, colour, &calibration.model);
+    }
+    chart.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_is_a_loss_for_both_fighters() {
+        // 75% for fighter one, who won, then 65% for fighter one in a draw
+        let calibration = calibrate(String::from("test"), &[(0.75, Winner::FighterOne), (0.65, Winner::Draw)], 10);
+
+        // (bin, count, wins) for each bin with anything in it
+        let filled = calibration.bins.iter().enumerate()
+            .filter(|(_, bin)| bin.count > 0)
+            .map(|(index, bin)| (index, bin.count, bin.wins))
+            .collect::<Vec<_>>();
+        // 25% and 35% lost, 65% drew so lost too, and only 75% won
+        assert_eq!(filled, vec![(2, 1, 0), (3, 1, 0), (6, 1, 0), (7, 1, 1)]);
+
+        // Fighter one: 0.25² and 0.65², fighter two: 0.25² and 0.35²
+        let expected_brier = (0.0625 + 0.4225 + 0.0625 + 0.1225) / 4f32;
+        assert!((calibration.brier - expected_brier).abs() < 1e-5);
+        assert_eq!(calibration.bouts, 2);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::format::{escape, format_timestamp};
+
+// Charts over time, i.e. a bout's history and the bankroll
+pub const CHART_WIDTH: f32 = 800f32;
+pub const CHART_HEIGHT: f32 = 400f32;
+// Charts of one probability against another, i.e. the reliability diagram
+pub const SQUARE_CHART_SIZE: f32 = 500f32;
+// Room around the plot for labels
+pub const CHART_MARGIN: f32 = 50f32;
+// Shown inline in the report
+pub const SPARKLINE_WIDTH: f32 = 160f32;
+pub const SPARKLINE_HEIGHT: f32 = 40f32;
+
+// An SVG chart, where points are given as fractions of the plot area inside the margin,
+// from (0, 0) at the bottom left to (1, 1) at the top right
+pub struct Chart {
+    width: f32,
+    height: f32,
+    margin: f32,
+    svg: String,
+}
+
+impl Chart {
+    // A white chart with the title across the top
+    pub fn new(width: f32, height: f32, title: &str) -> Chart {
+        let mut chart = Chart { width, height, margin: CHART_MARGIN, svg: String::new() };
+        chart.svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n\
+             <text x=\"{m}\" y=\"25\" font-size=\"16\">{}</text>\n",
+            escape(title),
+            w = width,
+            h = height,
+            m = CHART_MARGIN,
+        );
+        chart
+    }
+
+    // Just the lines, with no background, title or margin, for small charts shown inline
+    pub fn sparkline(width: f32, height: f32) -> Chart {
+        Chart {
+            width,
+            height,
+            margin: 0f32,
+            svg: format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">", w = width, h = height),
+        }
+    }
+
+    // Where a fraction of the way across the plot is
+    pub fn x(&self, fraction: f32) -> f32 {
+        self.margin + fraction * (self.width - 2f32 * self.margin)
+    }
+
+    // Where a fraction of the way up the plot is
+    pub fn y(&self, fraction: f32) -> f32 {
+        self.height - self.margin - fraction * (self.height - 2f32 * self.margin)
+    }
+
+    pub fn push_str(&mut self, svg: &str) {
+        self.svg.push_str(svg);
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, anchor: &str, text: &str) {
+        self.svg.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"{}\">{}</text>\n", x, y, anchor, escape(text)));
+    }
+
+    // All the way across the plot, a fraction of the way up
+    pub fn horizontal_line(&mut self, fraction: f32, colour: &str, dashed: bool) {
+        self.svg.push_str(&format!(
+            "<line x1=\"{}\" y1=\"{py}\" x2=\"{}\" y2=\"{py}\" stroke=\"{}\"{}/>\n",
+            self.x(0f32),
+            self.x(1f32),
+            colour,
+            if dashed { " stroke-dasharray=\"6 4\"" } else { "" },
+            py = self.y(fraction),
+        ));
+    }
+
+    // Gridlines every 25% up the plot, labelled down the left
+    pub fn percent_gridlines(&mut self) {
+        for percent in (0..=100).step_by(25) {
+            let fraction = percent as f32 / 100f32;
+            self.horizontal_line(fraction, "#dddddd", false);
+            self.text(self.x(0f32) - 5f32, self.y(fraction) + 4f32, "end", &format!("{}%", percent));
+        }
+    }
+
+    // The first and last times, under either end of the plot
+    pub fn time_labels(&mut self, start: i64, end: i64) {
+        let y = self.y(0f32) + 20f32;
+        self.text(self.x(0f32), y, "start", &format_timestamp(start));
+        self.text(self.x(1f32), y, "end", &format_timestamp(end));
+    }
+
+    pub fn polyline(&mut self, points: &[(f32, f32)], colour: &str, stroke_width: f32, dashed: bool) {
+        self.svg.push_str(&format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"{}/>",
+            points.iter().map(|(px, py)| format!("{:.1},{:.1}", self.x(*px), self.y(*py))).collect::<Vec<_>>().join(" "),
+            colour,
+            stroke_width,
+            if dashed { " stroke-dasharray=\"6 4\"" } else { "" },
+        ));
+        // Sparklines are inline in HTML, so are kept to one line
+        if self.margin > 0f32 {
+            self.svg.push('\n');
+        }
+    }
+
+    // A short line in the colour, then the label, starting at (x, y) in pixels
+    pub fn legend_entry(&mut self, x: f32, y: f32, colour: &str, label: &str) {
+        self.svg.push_str(&format!(
+            "<line x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\" stroke=\"{}\" stroke-width=\"2\"/>\n",
+            x,
+            x + 20f32,
+            colour,
+            y = y,
+        ));
+        self.text(x + 25f32, y + 4f32, "start", label);
+    }
+
+    pub fn finish(mut self) -> String {
+        self.svg.push_str("</svg>");
+        if self.margin > 0f32 {
+            self.svg.push('\n');
+        }
+        self.svg
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt::Arguments;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+// Where messages are printed. Normally that's stdout and stderr, but the TUI takes them over while it's running
+// so they're shown in its log pane rather than drawn over the UI
+static TAKEN_OVER: Mutex<Option<File>> = Mutex::new(None);
+
+// Like println!, but goes wherever messages are going
+macro_rules! outln {
+    ($($arg:tt)*) => { $crate::console::write_line(true, format_args!($($arg)*)) };
+}
+
+// Like eprintln!, but goes wherever messages are going
+macro_rules! errln {
+    ($($arg:tt)*) => { $crate::console::write_line(false, format_args!($($arg)*)) };
+}
+
+// Sends everything printed to the file until give_back is called
+pub fn take_over(file: File) {
+    *TAKEN_OVER.lock().unwrap() = Some(file);
+}
+
+pub fn give_back() {
+    *TAKEN_OVER.lock().unwrap() = None;
+}
+
+pub fn stdout() -> Box<dyn Write> {
+    taken_over().unwrap_or_else(|| Box::new(io::stdout()))
+}
+
+pub fn stderr() -> Box<dyn Write> {
+    taken_over().unwrap_or_else(|| Box::new(io::stderr()))
+}
+
+fn taken_over() -> Option<Box<dyn Write>> {
+    let file = TAKEN_OVER.lock().ok()?.as_ref()?.try_clone().ok()?;
+    Some(Box::new(file))
+}
+
+// There's nowhere left to say a message couldn't be printed, so failures are ignored like they are for println!
+pub fn write_line(to_stdout: bool, args: Arguments) {
+    let mut out = if to_stdout { stdout() } else { stderr() };
+    let _ = writeln!(out, "{}", args);
+}
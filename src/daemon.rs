@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDate};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::flag;
+use tiny_http::Server;
+
+use crate::api;
+use crate::boxrec::UnattendedPicker;
+use crate::storage::Storage;
+use crate::{scan_once, Args, Config, Session, CONFIG_PATH};
+
+// Scans start speeding up this many days before fight night
+const RAMP_UP_DAYS: i64 = 7;
+// How often to check for signals (and API requests) while waiting for the next scan
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn run_daemon(mut config: Config, args: &Args) -> Result<(), Box<dyn Error>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let reload = Arc::new(AtomicBool::new(false));
+    for signal in &[SIGTERM, SIGINT] {
+        // A second signal kills us straight away, in case a scan's stuck
+        flag::register_conditional_shutdown(*signal, 1, Arc::clone(&stop))?;
+        flag::register(*signal, Arc::clone(&stop))?;
+    }
+    flag::register(SIGHUP, Arc::clone(&reload))?;
+
+    println!("Running as a daemon, send SIGHUP to reload the config or SIGTERM to stop");
+    // Kept between scans so we only log in to BoxRec once, and dropped on reload in case the login changed
+    let mut session: Option<Session> = None;
+    let mut server = start_api(&config);
+    while !stop.load(Ordering::Relaxed) {
+        if session.is_none() {
+            session = match Session::open(&config, args) {
+                Ok(Some(mut session)) => {
+                    // Nobody's watching, so ambiguous searches and reCAPTCHAs fail the lookup rather than wait forever
+                    session.boxrec.set_picker(Box::new(UnattendedPicker));
+                    Some(session)
+                },
+                Ok(None) => {
+                    eprintln!("Another run is using the cache, trying again next scan");
+                    None
+                },
+                Err(err) => {
+                    eprintln!("Failed to connect, trying again next scan (Error: {})", err);
+                    None
+                },
+            };
+        }
+
+        let mut next_fight = None;
+        if let Some(session) = &mut session {
+            // A bad scan (i.e. Betfair being down) shouldn't take the daemon with it
+            if let Err(err) = scan_once(&config, args, session, &mut ()) {
+                eprintln!("Scan failed (Error: {})", err);
+            }
+            next_fight = match find_next_fight(&session.storage) {
+                Ok(next_fight) => next_fight,
+                Err(err) => {
+                    eprintln!("Failed to look up the next fight (Error: {})", err);
+                    None
+                },
+            };
+        }
+
+        let interval = scan_interval(&config, next_fight);
+        println!("Next scan in {} minute(s)", interval.as_secs() / 60);
+        let next_scan = Instant::now() + interval;
+        let mut rescan = false;
+        while Instant::now() < next_scan && !rescan && !stop.load(Ordering::Relaxed) && !reload.load(Ordering::Relaxed) {
+            // Requests are answered in between scans, so they never fight a scan over BoxRec or the database
+            match &server {
+                Some(server) => match server.recv_timeout(POLL_INTERVAL) {
+                    Ok(Some(request)) => rescan = api::handle(request, session.as_mut()),
+                    Ok(None) => (),
+                    Err(err) => {
+                        eprintln!("Failed to receive API request (Error: {})", err);
+                        thread::sleep(POLL_INTERVAL);
+                    },
+                },
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+
+        // Reloading scans again straight away, so there's something to show for the new config
+        if reload.swap(false, Ordering::Relaxed) {
+            match Config::load(CONFIG_PATH) {
+                Ok(reloaded) => {
+                    println!("Reloaded config");
+                    config = reloaded;
+                    session = None;
+                    // Let go of the old address first, in case it hasn't changed
+                    drop(server.take());
+                    server = start_api(&config);
+                },
+                Err(err) => eprintln!("Failed to reload config, keeping the current one (Error: {})", err),
+            }
+        }
+    }
+
+    println!("Stopping");
+    Ok(())
+}
+
+fn start_api(config: &Config) -> Option<Server> {
+    let address = config.api_address.as_ref()?;
+    match Server::http(address) {
+        Ok(server) => {
+            println!("HTTP API listening on {}", address);
+            Some(server)
+        },
+        Err(err) => {
+            eprintln!("Failed to start HTTP API on {}, carrying on without it (Error: {})", address, err);
+            None
+        },
+    }
+}
+
+// The soonest date any unsettled bout is due to take place on, if we know any of their dates
+fn find_next_fight(storage: &Storage) -> Result<Option<NaiveDate>, Box<dyn Error>> {
+    let today = Local::now().date_naive();
+    Ok(storage.load_bouts()?
+        .iter()
+        .filter(|metadata| !metadata.status.is_settled())
+        .filter_map(|metadata| metadata.event.as_ref()?.date)
+        .filter(|date| *date >= today)
+        .min())
+}
+
+// Scans every scan_interval_minutes normally, closing in on fight_night_scan_interval_minutes over
+// the last week before a fight
+fn scan_interval(config: &Config, next_fight: Option<NaiveDate>) -> Duration {
+    let normal = config.get_scan_interval_minutes();
+    let fight_night = config.get_fight_night_scan_interval_minutes().min(normal);
+    let minutes = match next_fight {
+        Some(date) => {
+            let days = (date - Local::now().date_naive()).num_days().clamp(0, RAMP_UP_DAYS) as u64;
+            fight_night + (normal - fight_night) * days / RAMP_UP_DAYS as u64
+        },
+        None => normal,
+    };
+    Duration::from_secs(minutes.max(1) * 60)
+}
@@ -0,0 +1,122 @@
+use std::error::Error;
+
+use crate::cache::atomic_write;
+use crate::chart::{Chart, CHART_HEIGHT, CHART_MARGIN, CHART_WIDTH};
+use crate::format::format_timestamp;
+use crate::odds::BoutOdds;
+use crate::storage::{BoutSummary, Storage, BOXREC_SCORE_MODEL};
+
+// Colours for fighter one, the draw and fighter two
+const LINE_COLOURS: [&str; 3] = ["#1f77b4", "#7f7f7f", "#d62728"];
+
+// Prints how Betfair's prices for a bout have moved, optionally drawing them as an SVG chart too
+pub fn show_history(storage: &Storage, bout: &str, chart_path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let bout = find_one_bout(storage, bout)?;
+    let snapshots = storage.odds_history(bout.id)?;
+    let model = storage.model_history(bout.id, BOXREC_SCORE_MODEL)?;
+
+    println!("{} vs. {} ({})", bout.fighter_one, bout.fighter_two, bout.status);
+    if snapshots.is_empty() {
+        println!("No odds recorded yet");
+        return Ok(());
+    }
+
+    println!("{:<17}  {:>15}  {:>15}  {:>15}", "Taken at", "1", "X", "2");
+    for (taken_at, odds) in &snapshots {
+        println!("{:<17}  {:>15}  {:>15}  {:>15}",
+                 format_timestamp(*taken_at),
+                 format_odds(&odds.one_wins.as_frac(), odds.one_wins.as_percent()),
+                 format_odds(&odds.draw.as_frac(), odds.draw.as_percent()),
+                 format_odds(&odds.two_wins.as_frac(), odds.two_wins.as_percent()));
+    }
+    if let Some((_, win_percent_one)) = model.last() {
+        println!("BoxRec scores give {} a {:.1}% chance of winning, and {} {:.1}%",
+                 bout.fighter_one,
+                 win_percent_one,
+                 bout.fighter_two,
+                 100f32 - win_percent_one);
+    }
+
+    if let Some(path) = chart_path {
+        atomic_write(path, render_chart(&bout, &snapshots, &model).as_bytes())?;
+        println!("Chart saved to {}", path);
+    }
+    Ok(())
+}
+
+// Bouts can be picked by ID, or by (part of) either fighter's name as long as only one matches
+pub fn find_one_bout(storage: &Storage, query: &str) -> Result<BoutSummary, Box<dyn Error>> {
+    if let Ok(id) = query.parse::<i64>() {
+        return storage.get_bout(id)?.ok_or_else(|| format!("No bout with ID {}", id).into());
+    }
+
+    let mut matches = storage.find_bouts(query)?;
+    match matches.len() {
+        0 => Err(format!("No bouts found matching \"{}\"", query).into()),
+        1 => Ok(matches.remove(0)),
+        _ => {
+            println!("More than one bout matches \"{}\", use one of these IDs instead:", query);
+            for bout in &matches {
+                println!("{}) {} vs. {} ({})", bout.id, bout.fighter_one, bout.fighter_two, bout.status);
+            }
+            Err("Ambiguous bout".into())
+        },
+    }
+}
+
+fn format_odds(frac: &str, percent: f32) -> String {
+    format!("{} ({:.1}%)", frac, percent)
+}
+
+fn render_chart(bout: &BoutSummary, snapshots: &[(i64, BoutOdds)], model: &[(i64, f32)]) -> String {
+    let start = snapshots.iter().map(|(t, _)| *t)
+        .chain(model.iter().map(|(t, _)| *t))
+        .min()
+        .unwrap_or(0);
+    let end = snapshots.iter().map(|(t, _)| *t)
+        .chain(model.iter().map(|(t, _)| *t))
+        .max()
+        .unwrap_or(0)
+        // Avoid dividing by zero if there's only one point in time
+        .max(start + 1);
+
+    let x = |t: i64| (t - start) as f32 / (end - start) as f32;
+    let y = |percent: f32| percent / 100f32;
+
+    let mut chart = Chart::new(CHART_WIDTH, CHART_HEIGHT, &format!("{} vs. {}", bout.fighter_one, bout.fighter_two));
+    chart.percent_gridlines();
+    chart.time_labels(start, end);
+
+    // Betfair's implied probabilities, solid lines
+    let names = [bout.fighter_one.as_str(), "Draw", bout.fighter_two.as_str()];
+    for (index, colour) in LINE_COLOURS.iter().enumerate() {
+        let points = snapshots.iter()
+            .map(|(t, odds)| (x(*t), y(implied_percents(odds)[index])))
+            .collect::<Vec<_>>();
+        chart.polyline(&points, colour, 2f32, false);
+    }
+
+    // Our model's chances for each fighter, dashed lines in the same colours
+    for (index, colour) in [LINE_COLOURS[0], LINE_COLOURS[2]].iter().enumerate() {
+        if model.is_empty() { break; }
+        let points = model.iter()
+            .map(|(t, win_percent_one)| {
+                let percent = if index == 0 { *win_percent_one } else { 100f32 - win_percent_one };
+                (x(*t), y(percent))
+            })
+            .collect::<Vec<_>>();
+        chart.polyline(&points, colour, 2f32, true);
+    }
+
+    // Legend
+    for (index, name) in names.iter().enumerate() {
+        chart.legend_entry(CHART_MARGIN + index as f32 * 200f32, CHART_HEIGHT - 12f32, LINE_COLOURS[index], name);
+    }
+    chart.text(CHART_WIDTH - CHART_MARGIN, CHART_HEIGHT - 8f32, "end", "dashed: BoxRec model");
+    chart.finish()
+}
+
+// Betfair's implied chance of fighter one winning, a draw, and fighter two winning
+fn implied_percents(odds: &BoutOdds) -> [f32; 3] {
+    [odds.one_wins.as_percent(), odds.draw.as_percent(), odds.two_wins.as_percent()]
+}
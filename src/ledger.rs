@@ -0,0 +1,219 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use crate::boxrec::Winner;
+use crate::cache::atomic_write;
+use crate::chart::{Chart, CHART_HEIGHT, CHART_WIDTH};
+use crate::format::format_timestamp;
+use crate::history::find_one_bout;
+use crate::odds::Odds;
+use crate::storage::{BoutSummary, Storage};
+use crate::BoutStatus;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BetOutcome {
+    Won,
+    Lost,
+    // Stake returned, i.e. the bout was cancelled or ended in a no contest
+    Void,
+}
+
+impl BetOutcome {
+    // How a bet on `selection` went, given how its bout was settled
+    pub fn from_result(selection: &Winner, status: BoutStatus, winner: Option<Winner>) -> BetOutcome {
+        match (status, winner) {
+            (BoutStatus::Completed, Some(Winner::NoContest)) => BetOutcome::Void,
+            (BoutStatus::Completed, Some(winner)) if &winner == selection => BetOutcome::Won,
+            (BoutStatus::Completed, Some(_)) => BetOutcome::Lost,
+            // Cancelled, expired, or we never found out who won
+            _ => BetOutcome::Void,
+        }
+    }
+
+    pub fn profit(&self, stake: f32, odds: &Odds) -> f32 {
+        match self {
+            BetOutcome::Won => stake * (odds.as_decimal() - 1f32),
+            BetOutcome::Lost => -stake,
+            BetOutcome::Void => 0f32,
+        }
+    }
+}
+
+impl Display for BetOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            BetOutcome::Won => "Won",
+            BetOutcome::Lost => "Lost",
+            BetOutcome::Void => "Void",
+        })
+    }
+}
+
+// Records a bet in the ledger, settling it straight away if the bout's already been settled
+pub fn place_bet(storage: &mut Storage,
+                 bout: &str,
+                 selection: &str,
+                 stake: f32,
+                 odds: &str,
+                 bookmaker: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let bout = find_one_bout(storage, bout)?;
+    let selection = parse_selection(&bout, selection)?;
+    if stake <= 0f32 {
+        return Err("Stake must be more than 0".into());
+    }
+    let odds = Odds::from_user(odds)?;
+
+    let id = storage.record_bet(bout.id, &selection, stake, &odds, bookmaker)?;
+    outln!("Recorded bet {}: {:.2} on {} at {} ({} vs. {})",
+           id,
+           stake,
+           selection_name(&selection, &bout.fighter_one, &bout.fighter_two),
+           odds,
+           bout.fighter_one,
+           bout.fighter_two);
+
+    if bout.status.is_settled() {
+        settle_bets(storage)?;
+    }
+    Ok(())
+}
+
+// Settles bets on any bouts that have been settled, printing how each went
+pub fn settle_bets(storage: &mut Storage) -> Result<(), Box<dyn Error>> {
+    for bet in storage.settle_bets()? {
+        errln!("Bet {} on {}: {} ({:+.2})",
+               bet.id,
+               selection_name(&bet.selection, &bet.fighter_one, &bet.fighter_two),
+               bet.outcome.unwrap(),
+               bet.profit.unwrap());
+    }
+    Ok(())
+}
+
+pub fn show_open_bets(storage: &Storage) -> Result<(), Box<dyn Error>> {
+    let bets = storage.open_bets()?;
+    if bets.is_empty() {
+        println!("No open bets");
+        return Ok(());
+    }
+
+    println!("{:>4}  {:<17}  {:<40}  {:<20}  {:>8}  {:>7}  {:>9}  Bookmaker",
+             "ID", "Placed at", "Bout", "On", "Stake", "Odds", "To return");
+    for bet in &bets {
+        println!("{:>4}  {:<17}  {:<40}  {:<20}  {:>8.2}  {:>7}  {:>9.2}  {}",
+                 bet.id,
+                 format_timestamp(bet.placed_at),
+                 format!("{} vs. {}", bet.fighter_one, bet.fighter_two),
+                 selection_name(&bet.selection, &bet.fighter_one, &bet.fighter_two),
+                 bet.stake,
+                 bet.odds.as_frac(),
+                 bet.stake * bet.odds.as_decimal(),
+                 bet.bookmaker.as_deref().unwrap_or(""));
+    }
+    let staked: f32 = bets.iter().map(|bet| bet.stake).sum();
+    println!("{} open bet(s), {:.2} staked", bets.len(), staked);
+    Ok(())
+}
+
+// Prints each settled bet with the running profit and bankroll, optionally charting the bankroll too
+pub fn show_pnl(storage: &Storage, starting_bankroll: f32, chart_path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let bets = storage.settled_bets()?;
+    if bets.is_empty() {
+        println!("No settled bets yet");
+        return Ok(());
+    }
+
+    println!("{:<17}  {:<40}  {:<20}  {:<4}  {:>8}  {:>8}  {:>8}  {:>9}",
+             "Settled at", "Bout", "On", "", "Stake", "Profit", "Total", "Bankroll");
+    let mut total = 0f32;
+    let mut curve = vec![(bets[0].placed_at, starting_bankroll)];
+    for bet in &bets {
+        let profit = bet.profit.unwrap_or(0f32);
+        let settled_at = bet.settled_at.unwrap_or(bet.placed_at);
+        total += profit;
+        curve.push((settled_at, starting_bankroll + total));
+        println!("{:<17}  {:<40}  {:<20}  {:<4}  {:>8.2}  {:>+8.2}  {:>+8.2}  {:>9.2}",
+                 format_timestamp(settled_at),
+                 format!("{} vs. {}", bet.fighter_one, bet.fighter_two),
+                 selection_name(&bet.selection, &bet.fighter_one, &bet.fighter_two),
+                 bet.outcome.map(|outcome| outcome.to_string()).unwrap_or_default(),
+                 bet.stake,
+                 profit,
+                 total,
+                 starting_bankroll + total);
+    }
+
+    let staked: f32 = bets.iter()
+        .filter(|bet| bet.outcome != Some(BetOutcome::Void))
+        .map(|bet| bet.stake)
+        .sum();
+    let won = bets.iter().filter(|bet| bet.outcome == Some(BetOutcome::Won)).count();
+    let lost = bets.iter().filter(|bet| bet.outcome == Some(BetOutcome::Lost)).count();
+    println!("{} won, {} lost, {} void. {:+.2} from {:.2} staked (ROI {:.1}%)",
+             won,
+             lost,
+             bets.len() - won - lost,
+             total,
+             staked,
+             if staked > 0f32 { 100f32 * total / staked } else { 0f32 });
+
+    if let Some(path) = chart_path {
+        atomic_write(path, render_bankroll_chart(&curve, starting_bankroll).as_bytes())?;
+        println!("Chart saved to {}", path);
+    }
+    Ok(())
+}
+
+// Bets can be on "1", "X" or "2" (as Betfair lists them), or on a fighter by (part of) their name
+fn parse_selection(bout: &BoutSummary, selection: &str) -> Result<Winner, Box<dyn Error>> {
+    let lowercase = selection.to_lowercase();
+    match lowercase.as_str() {
+        "1" => return Ok(Winner::FighterOne),
+        "2" => return Ok(Winner::FighterTwo),
+        "x" | "draw" => return Ok(Winner::Draw),
+        _ => (),
+    }
+
+    let on_one = bout.fighter_one.to_lowercase().contains(&lowercase);
+    let on_two = bout.fighter_two.to_lowercase().contains(&lowercase);
+    match (on_one, on_two) {
+        (true, false) => Ok(Winner::FighterOne),
+        (false, true) => Ok(Winner::FighterTwo),
+        (true, true) => Err(format!("\"{}\" matches both fighters, use 1 or 2 instead", selection).into()),
+        (false, false) => Err(format!("\"{}\" isn't in this bout, use 1, X or 2", selection).into()),
+    }
+}
+
+fn selection_name(selection: &Winner, fighter_one: &str, fighter_two: &str) -> String {
+    match selection {
+        Winner::FighterOne => String::from(fighter_one),
+        Winner::FighterTwo => String::from(fighter_two),
+        Winner::Draw => String::from("Draw"),
+        Winner::NoContest => String::from("No contest"),
+    }
+}
+
+fn render_bankroll_chart(curve: &[(i64, f32)], starting_bankroll: f32) -> String {
+    let start = curve.first().map(|(t, _)| *t).unwrap_or(0);
+    // Avoid dividing by zero if everything happened at once
+    let end = curve.last().map(|(t, _)| *t).unwrap_or(0).max(start + 1);
+    let low = curve.iter().map(|(_, b)| *b).fold(starting_bankroll, f32::min);
+    let high = curve.iter().map(|(_, b)| *b).fold(starting_bankroll, f32::max).max(low + 1f32);
+
+    let x = |t: i64| (t - start) as f32 / (end - start) as f32;
+    let y = |b: f32| (b - low) / (high - low);
+
+    let mut chart = Chart::new(CHART_WIDTH, CHART_HEIGHT, "Bankroll");
+    // Dashed line at the starting bankroll, so it's easy to see whether we're up or down
+    chart.horizontal_line(y(starting_bankroll), "#999999", true);
+    for bankroll in &[low, high] {
+        chart.text(chart.x(0f32) - 5f32, chart.y(y(*bankroll)) + 4f32, "end", &format!("{:.0}", bankroll));
+    }
+    chart.time_labels(start, end);
+
+    let points = curve.iter()
+        .map(|(t, b)| (x(*t), y(*b)))
+        .collect::<Vec<_>>();
+    chart.polyline(&points, "#1f77b4", 2f32, false);
+    chart.finish()
+}
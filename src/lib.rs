@@ -1,23 +1,152 @@
 #![allow(dead_code)]
 
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
-use std::fs::{self, OpenOptions};
-use std::io::{ErrorKind, Write};
+use std::fs;
 
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+use tokio::runtime::{self, Runtime};
 
 use boxer::*;
 
-use crate::betfair::{BetfairAPI, Bout};
-use crate::boxrec::BoxRecAPI;
-
+use crate::arbitrage::Arbitrage;
+use crate::betfair::exchange::ExchangeConfig;
+use crate::betfair::BetfairMarket;
+use crate::boxrec::{BoutResult, BoxRecAPI, Event};
+use crate::cache::{atomic_write, create_cache_dir, CacheLock};
+use crate::notify::email::EmailConfig;
+use crate::notify::webhook::WebhookConfig;
+use crate::notify::{SinkConfig, SinkKind};
+use crate::notify::{Alert, Position};
+use crate::odds::{Bout, Odds, OddsSources, SourceConfig};
+use crate::output::{OutputFormat, ScanResult};
+use crate::storage::{now, Storage, BOXREC_SCORE_MODEL};
+
+// First, so its macros can be used everywhere else
+#[macro_use]
+mod console;
+mod api;
+mod arbitrage;
+mod backtest;
 mod betfair;
 mod boxer;
 mod boxrec;
+mod cache;
+mod calibration;
+mod chart;
+mod daemon;
+mod format;
+mod history;
+mod ledger;
+mod lifecycle;
+mod notify;
+mod odds;
+mod output;
+mod pagecache;
+mod ratelimit;
+mod report;
+mod storage;
+mod tui;
 
 const CONFIG_PATH: &str = "./config.yml";
+// How many bouts (or boxers) are looked into at once, BoxRec's rate limiter still decides when requests go out
+const CONCURRENT_LOOKUPS: usize = 8;
+
+#[derive(StructOpt)]
+#[structopt(about = "Compares Betfair's boxing odds against odds worked out from BoxRec")]
+pub struct Args {
+    /// Ignore cached BoxRec pages and download everything afresh
+    #[structopt(long)]
+    pub refresh: bool,
+
+    /// If another run is using the cache, wait for it to finish instead of exiting
+    #[structopt(long)]
+    pub wait: bool,
+
+    /// Sort the end of run summary by the biggest edge, rather than in the order bouts were found
+    #[structopt(long)]
+    pub sort_by_edge: bool,
+
+    /// Also write out every bout checked, as jsonl (one JSON object per line) or csv
+    #[structopt(long)]
+    pub format: Option<OutputFormat>,
+
+    /// Where to write --format output to, instead of stdout
+    #[structopt(long, requires = "format")]
+    pub output: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// Check Betfair for bouts and compare their odds against BoxRec (the default)
+    Scan,
+    /// Keep running and scan on a schedule, more often as fight night gets closer
+    Daemon,
+    /// Scan in an interactive terminal UI, showing each bout as it's checked
+    Tui,
+    /// Show how Betfair's odds for a bout have moved over time
+    History {
+        /// The bout's ID, or part of either fighter's name
+        bout: String,
+        /// Also draw the odds as a line chart, saved as an SVG to this path
+        #[structopt(long)]
+        chart: Option<String>,
+    },
+    /// Replay stored odds and BoxRec scores against settled bouts, to see how betting on them would have gone
+    Backtest {
+        /// Edges (in percentage points) to try betting at, defaults to notify_threshold
+        #[structopt(long, use_delimiter = true)]
+        thresholds: Vec<f32>,
+        /// Fraction of the full Kelly stake to bet
+        #[structopt(long, default_value = "0.5")]
+        kelly_fraction: f32,
+    },
+    /// Check how well the predicted chances of winning match up with what actually happened
+    Calibration {
+        /// How many equal width bins to split the predicted chances into
+        #[structopt(long, default_value = "10")]
+        bins: usize,
+        /// Save the binned results as CSV to this path
+        #[structopt(long)]
+        csv: Option<String>,
+        /// Save a reliability diagram as an SVG to this path
+        #[structopt(long)]
+        chart: Option<String>,
+    },
+    /// Keep track of bets you've placed
+    Bets(BetsCommand),
+}
+
+#[derive(StructOpt)]
+pub enum BetsCommand {
+    /// Record a bet against a bout
+    Place {
+        /// The bout's ID, or part of either fighter's name
+        bout: String,
+        /// What the bet's on: 1, X or 2, or (part of) a fighter's name
+        selection: String,
+        stake: f32,
+        /// The odds taken, fractional (6/4), decimal (2.5) or evens
+        odds: String,
+        #[structopt(long)]
+        bookmaker: Option<String>,
+    },
+    /// List bets that haven't been settled yet
+    Open,
+    /// Show profit and loss from settled bets, and how the bankroll has moved
+    Pnl {
+        /// Also draw the bankroll as a line chart, saved as an SVG to this path
+        #[structopt(long)]
+        chart: Option<String>,
+    },
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
@@ -25,7 +154,30 @@ pub struct Config {
     pub username: Option<String>,
     pub password: Option<String>,
     request_timeout: Option<u64>,
+    requests_per_minute: Option<u32>,
+    daily_request_budget: Option<u32>,
+    search_ttl_hours: Option<u64>,
+    profile_ttl_hours: Option<u64>,
+    event_ttl_hours: Option<u64>,
     notify_threshold: Option<f32>,
+    lay_threshold: Option<f32>,
+    lay_liability: Option<f32>,
+    arbitrage_outlay: Option<f32>,
+    arbitrage_min_profit: Option<f32>,
+    exchange_commission: Option<f32>,
+    prune_settled_after_days: Option<u64>,
+    starting_bankroll: Option<f32>,
+    scan_interval_minutes: Option<u64>,
+    fight_night_scan_interval_minutes: Option<u64>,
+    api_address: Option<String>,
+    betfair_market: Option<BetfairMarket>,
+    betfair_exchange: Option<ExchangeConfig>,
+    // Replaces betfair_market/betfair_exchange when set
+    odds_sources: Option<Vec<SourceConfig>>,
+    notifiers: Option<Vec<SinkConfig>>,
+    // Shorthands for a webhook or email sink with no filters, from before notifiers existed
+    webhook: Option<WebhookConfig>,
+    email: Option<EmailConfig>,
 }
 
 impl Config {
@@ -34,17 +186,22 @@ impl Config {
             Ok(contents) => match serde_yaml::from_str(contents.as_str()) {
                 Ok(config) => config,
                 Err(err) => {
-                    eprintln!("Failed to parse config file, using default (Error: {})", err);
+                    errln!("Failed to parse config file, using default (Error: {})", err);
                     Config::new_default()
                 },
             },
             Err(err) => {
-                eprintln!("Failed to read config file, using default (Error: {})", err);
+                errln!("Failed to read config file, using default (Error: {})", err);
                 Config::new_default()
             },
         }
     }
 
+    // Unlike new, doesn't fall back to the defaults, so a daemon can keep its current config instead
+    fn load(path: &str) -> Result<Config, Box<dyn Error>> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
     fn new_default() -> Config {
         // Sensible defaults™
         Config {
@@ -52,22 +209,38 @@ impl Config {
             username: None,
             password: None,
             request_timeout: Some(500u64),
+            requests_per_minute: Some(20u32),
+            daily_request_budget: Some(500u32),
+            search_ttl_hours: Some(7u64 * 24),
+            profile_ttl_hours: Some(24u64),
+            event_ttl_hours: Some(6u64),
             notify_threshold: Some(15f32),
+            lay_threshold: Some(15f32),
+            lay_liability: Some(10f32),
+            arbitrage_outlay: Some(100f32),
+            arbitrage_min_profit: Some(1f32),
+            exchange_commission: Some(5f32), // Betfair's usual rate
+            prune_settled_after_days: None, // Keep settled bouts forever by default
+            starting_bankroll: Some(100f32),
+            scan_interval_minutes: Some(60u64),
+            fight_night_scan_interval_minutes: Some(10u64),
+            api_address: None, // No HTTP API by default
+            betfair_market: Some(BetfairMarket::Sportsbook),
+            betfair_exchange: None,
+            odds_sources: None, // Just the Betfair market by default
+            notifiers: Some(vec![SinkConfig::unfiltered(SinkKind::Stdout)]), // Just print alerts by default
+            webhook: None,
+            email: None,
         }
     }
 
     fn save(&self) -> Result<(), Box<dyn Error>> {
         let ser = serde_yaml::to_string(&self)?;
-        match OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(CONFIG_PATH)?
-            .write_all(ser.as_bytes())
-        {
+        match atomic_write(CONFIG_PATH, ser.as_bytes()) {
             Ok(_) => Ok(()),
             Err(err) => {
-                eprintln!("Failed to save config file (Error: {})", err);
-                eprintln!("Here's the config if you wanted it:\n{}", ser);
+                errln!("Failed to save config file (Error: {})", err);
+                errln!("Here's the config if you wanted it:\n{}", ser);
                 Err(err.into())
             },
         }
@@ -80,32 +253,197 @@ impl Config {
         }
     }
 
+    pub fn get_requests_per_minute(&self) -> u32 {
+        match &self.requests_per_minute {
+            Some(n) => *n,
+            None => Config::new_default().requests_per_minute.unwrap(),
+        }
+    }
+
+    pub fn get_daily_request_budget(&self) -> u32 {
+        match &self.daily_request_budget {
+            Some(n) => *n,
+            None => Config::new_default().daily_request_budget.unwrap(),
+        }
+    }
+
+    pub fn get_search_ttl_hours(&self) -> u64 {
+        match &self.search_ttl_hours {
+            Some(hours) => *hours,
+            None => Config::new_default().search_ttl_hours.unwrap(),
+        }
+    }
+
+    pub fn get_profile_ttl_hours(&self) -> u64 {
+        match &self.profile_ttl_hours {
+            Some(hours) => *hours,
+            None => Config::new_default().profile_ttl_hours.unwrap(),
+        }
+    }
+
+    pub fn get_event_ttl_hours(&self) -> u64 {
+        match &self.event_ttl_hours {
+            Some(hours) => *hours,
+            None => Config::new_default().event_ttl_hours.unwrap(),
+        }
+    }
+
     pub fn get_notify_threshold(&self) -> f32 {
         match &self.notify_threshold {
             Some(percent) => *percent,
             None => Config::new_default().notify_threshold.unwrap(),
         }
     }
+
+    pub fn get_lay_threshold(&self) -> f32 {
+        match &self.lay_threshold {
+            Some(percent) => *percent,
+            None => Config::new_default().lay_threshold.unwrap(),
+        }
+    }
+
+    pub fn get_lay_liability(&self) -> f32 {
+        match &self.lay_liability {
+            Some(liability) => *liability,
+            None => Config::new_default().lay_liability.unwrap(),
+        }
+    }
+
+    pub fn get_arbitrage_outlay(&self) -> f32 {
+        match &self.arbitrage_outlay {
+            Some(outlay) => *outlay,
+            None => Config::new_default().arbitrage_outlay.unwrap(),
+        }
+    }
+
+    pub fn get_arbitrage_min_profit(&self) -> f32 {
+        match &self.arbitrage_min_profit {
+            Some(min_profit) => *min_profit,
+            None => Config::new_default().arbitrage_min_profit.unwrap(),
+        }
+    }
+
+    pub fn get_exchange_commission(&self) -> f32 {
+        match &self.exchange_commission {
+            Some(commission) => *commission,
+            None => Config::new_default().exchange_commission.unwrap(),
+        }
+    }
+
+    pub fn get_starting_bankroll(&self) -> f32 {
+        match &self.starting_bankroll {
+            Some(bankroll) => *bankroll,
+            None => Config::new_default().starting_bankroll.unwrap(),
+        }
+    }
+
+    pub fn get_scan_interval_minutes(&self) -> u64 {
+        match &self.scan_interval_minutes {
+            Some(minutes) => *minutes,
+            None => Config::new_default().scan_interval_minutes.unwrap(),
+        }
+    }
+
+    pub fn get_fight_night_scan_interval_minutes(&self) -> u64 {
+        match &self.fight_night_scan_interval_minutes {
+            Some(minutes) => *minutes,
+            None => Config::new_default().fight_night_scan_interval_minutes.unwrap(),
+        }
+    }
+
+    pub fn get_betfair_market(&self) -> BetfairMarket {
+        match &self.betfair_market {
+            Some(market) => *market,
+            None => Config::new_default().betfair_market.unwrap(),
+        }
+    }
+
+    pub fn get_odds_sources(&self) -> Result<Vec<SourceConfig>, Box<dyn Error>> {
+        if let Some(sources) = &self.odds_sources {
+            return Ok(sources.clone());
+        }
+        Ok(vec![match self.get_betfair_market() {
+            BetfairMarket::Sportsbook => SourceConfig::BetfairSportsbook,
+            BetfairMarket::Exchange => match &self.betfair_exchange {
+                Some(exchange_config) => SourceConfig::BetfairExchange(exchange_config.clone()),
+                None => return Err("betfair_market is exchange, but betfair_exchange isn't set".into()),
+            },
+        }])
+    }
+
+    pub fn get_notifiers(&self) -> Vec<SinkConfig> {
+        let mut notifiers = match &self.notifiers {
+            Some(notifiers) => notifiers.clone(),
+            None => Config::new_default().notifiers.unwrap(),
+        };
+        if let Some(webhook) = &self.webhook {
+            notifiers.push(SinkConfig::unfiltered(SinkKind::Webhook(webhook.clone())));
+        }
+        if let Some(email) = &self.email {
+            notifiers.push(SinkConfig::unfiltered(SinkKind::Email(email.clone())));
+        }
+        notifiers
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct BoutMetadata (
-    Bout, // TODO: Maybe take a reference so memory usage isn't as ass?
-    BoutStatus,
-);
+#[derive(Clone)]
+struct BoutMetadata {
+    id: Option<i64>, // None until the bout has been saved
+    bout: Bout,
+    status: BoutStatus,
+    event: Option<Event>, // Found along with the bout page
+    result: Option<BoutResult>,
+    last_seen: i64, // When Betfair last listed the bout
+    settled_at: Option<i64>,
+    edge_side: Option<Side>, // Who we last found to be over the notify threshold, if anyone
+    lay_side: Option<Side>, // Likewise for the lay threshold
+    arbitrage: bool, // Whether the prices were an arbitrage last time they were checked
+    odds_moved: bool, // Not saved, set when Betfair's prices have changed since last run
+}
 
+impl BoutMetadata {
+    fn new(bout: Bout) -> BoutMetadata {
+        BoutMetadata {
+            id: None,
+            bout,
+            status: BoutStatus::MissingBoxers,
+            event: None,
+            result: None,
+            last_seen: now(),
+            settled_at: None,
+            edge_side: None,
+            lay_side: None,
+            arbitrage: false,
+            odds_moved: false,
+        }
+    }
+}
+
+// Bouts are the same if they're between the same fighters, whichever way round they're listed, prices can change
+// Settled bouts are never loaded, so a rematch won't be mistaken for the original bout
 impl PartialEq for BoutMetadata {
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        odds::same_bout(&self.bout, &other.bout)
     }
 }
 
+// One side of a bout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    One,
+    Two,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
 enum BoutStatus {
     MissingBoxers,
     MissingBoutPage,
     Checked,
     Announced,
+    // Settled statuses, bouts with these are finished with
+    Completed,
+    Cancelled,
+    Expired,
 }
 
 impl BoutStatus {
@@ -116,9 +454,15 @@ impl BoutStatus {
             BoutStatus::MissingBoutPage => BoutStatus::Checked,
             BoutStatus::Checked => BoutStatus::Announced,
             BoutStatus::Announced => panic!("No next status (called on BoutStatus::Announced)"),
+            // Settled bouts are moved on by the lifecycle, not next()
+            settled => panic!("No next status (called on settled status '{}')", settled),
         };
         //println!(" to '{}'", self);
     }
+
+    fn is_settled(&self) -> bool {
+        matches!(self, BoutStatus::Completed | BoutStatus::Cancelled | BoutStatus::Expired)
+    }
 }
 
 impl Display for BoutStatus {
@@ -128,141 +472,492 @@ impl Display for BoutStatus {
             BoutStatus::MissingBoutPage => "Missing bout page",
             BoutStatus::Checked => "Odds compared between BoxRec & Betfair",
             BoutStatus::Announced => "User notified of odds differential",
+            BoutStatus::Completed => "Bout has taken place",
+            BoutStatus::Cancelled => "Bout was cancelled",
+            BoutStatus::Expired => "Bout passed without a result",
         })
     }
 }
 
-fn compare_and_notify(matchup: &Matchup, bout: &Bout, threshold: &f32) -> BoutStatus {
+// When a bout is worth telling the user about
+struct Thresholds {
+    // How far (in percentage points) our chance of a fighter winning has to beat the back price's
+    back: f32,
+    // How far the lay price's chance has to beat ours, exchange only as the Sportsbook can't be laid
+    lay: f32,
+    // The most a suggested lay should lose, if the fighter wins after all
+    lay_liability: f32,
+}
+
+impl Thresholds {
+    fn new(config: &Config) -> Thresholds {
+        Thresholds {
+            back: config.get_notify_threshold(),
+            lay: config.get_lay_threshold(),
+            lay_liability: config.get_lay_liability(),
+        }
+    }
+}
+
+// Notifies if either fighter's chances are underrated (to back them) or overrated (to lay them) by more than
+// the thresholds, but only when they first cross them, so the same edge isn't announced every time the bout is checked
+fn compare_and_notify(matchup: &Matchup, bout: &Bout, thresholds: &Thresholds, edge_side: &mut Option<Side>, lay_side: &mut Option<Side>,
+                      storage: &Storage, bout_id: i64) -> BoutStatus {
     /*println!("Ours: {}%\tBetfair's:{}%\nOurs: {}%\tBetfair's:{}%",
              matchup.win_percent_one,
              bout.odds.one_wins.as_percent(),
              matchup.win_percent_two,
              bout.odds.two_wins.as_percent(),
     );*/
-    if matchup.win_percent_one - bout.odds.one_wins.as_percent() > *threshold {
-        pretty_print_notification(
-            &matchup.fighter_one.get_name(),
-            &matchup.win_percent_one,
-            &matchup.fighter_two.get_name(),
-            &bout.odds.one_wins.as_frac(),
-            &matchup.warning,
-        );
-        BoutStatus::Announced
-    } else if matchup.win_percent_two - bout.odds.two_wins.as_percent() > *threshold {
-        pretty_print_notification(
-            &matchup.fighter_two.get_name(),
-            &matchup.win_percent_two,
-            &matchup.fighter_one.get_name(),
-            &bout.odds.two_wins.as_frac(),
-            &matchup.warning,
-        );
+    let mut announced = false;
+    let notify = |side: Side, odds: Odds, source: Option<String>, position: Position| {
+        let (fighter, win_percent, opponent, scores) = match side {
+            Side::One => (matchup.fighter_one, matchup.win_percent_one, matchup.fighter_two, matchup.scores),
+            Side::Two => (matchup.fighter_two, matchup.win_percent_two, matchup.fighter_one, matchup.scores.map(|(one, two)| (two, one))),
+        };
+        let alert = Alert {
+            fighter: fighter.get_name(),
+            opponent: opponent.get_name(),
+            win_percent: Some(win_percent),
+            odds: Some(odds),
+            source,
+            position,
+            warning: matchup.warning,
+            scores,
+            created_at: now(),
+            legs: Vec::new(),
+        };
+        // The sinks pick this up from the database at the end of the run
+        if let Err(err) = storage.record_notification(bout_id, &alert) {
+            errln!("Failed to record notification (Error: {})", err);
+        }
+    };
+
+    let side = if matchup.win_percent_one - bout.odds.one_wins.as_percent() > thresholds.back {
+        Some(Side::One)
+    } else if matchup.win_percent_two - bout.odds.two_wins.as_percent() > thresholds.back {
+        Some(Side::Two)
+    } else {
+        None
+    };
+    if let Some(side) = newly_crossed(side, edge_side) {
+        let (odds, source) = match side {
+            Side::One => (bout.odds.one_wins, bout.sources.as_ref().map(|sources| sources.one_wins.clone())),
+            Side::Two => (bout.odds.two_wins, bout.sources.as_ref().map(|sources| sources.two_wins.clone())),
+        };
+        notify(side, odds, source, Position::Back);
+        announced = true;
+    }
+
+    // Without exchange prices there's nothing to lay, so whatever we last found stands until there is
+    if let Some(exchange) = &bout.exchange {
+        let overrated = |win_percent: f32, lay: Option<Odds>| {
+            lay.filter(|lay| lay.as_percent() - win_percent > thresholds.lay)
+        };
+        let side = if overrated(matchup.win_percent_one, exchange.one.lay).is_some() {
+            Some(Side::One)
+        } else if overrated(matchup.win_percent_two, exchange.two.lay).is_some() {
+            Some(Side::Two)
+        } else {
+            None
+        };
+        if let Some(side) = newly_crossed(side, lay_side) {
+            let lay = match side {
+                Side::One => exchange.one.lay,
+                Side::Two => exchange.two.lay,
+            }.unwrap();
+            // Sized so that losing (the fighter winning) costs the liability and no more
+            let liability = thresholds.lay_liability;
+            let stake = liability / (lay.as_decimal() - 1f32);
+            notify(side, lay, Some(betfair::exchange::SOURCE_NAME.to_string()), Position::Lay { stake, liability });
+            announced = true;
+        }
+    }
+
+    if announced {
         BoutStatus::Announced
     } else {
         BoutStatus::Checked
     }
 }
 
-fn pretty_print_notification(winner_to_be: &str, win_percent: &f32, loser_to_be: &str, odds: &str, warning: &bool) {
-    println!("---\n{}\
-    We might be onto something chief!\n\
-    BoxRec shows {} as having a {}% chance of winning against {}, and yet the betting odds are {}\n\
-    ---",
-             if *warning { "[WARNING: both boxer's have a BoxRec score below the safe threshold]\n" } else { "" },
-             winner_to_be,
-             win_percent,
-             loser_to_be,
-             odds
-    );
+// Notifies when a bout's prices first add up to less than 100%, i.e. backing every outcome can't lose
+// Doesn't need the model, so bouts get checked as soon as they're listed
+fn check_arbitrage(metadata: &mut BoutMetadata, config: &Config) -> Option<Alert> {
+    let arbitrage = Arbitrage::find(&metadata.bout,
+                                    config.get_arbitrage_outlay(),
+                                    config.get_exchange_commission(),
+                                    config.get_arbitrage_min_profit());
+    let was_arbitrage = std::mem::replace(&mut metadata.arbitrage, arbitrage.is_some());
+    let arbitrage = arbitrage.filter(|_| !was_arbitrage)?;
+
+    Some(Alert {
+        fighter: metadata.bout.fighter_one.clone(),
+        opponent: metadata.bout.fighter_two.clone(),
+        win_percent: None,
+        odds: None,
+        source: None,
+        position: Position::Arbitrage {
+            book_percent: arbitrage.book_percent,
+            outlay: arbitrage::outlay(&arbitrage.legs),
+            profit: arbitrage::profit(&arbitrage.legs),
+        },
+        warning: false,
+        scores: None,
+        created_at: now(),
+        legs: arbitrage.legs,
+    })
+}
+
+// Records which side is over a threshold now, returning it if it wasn't last time (i.e. the user hasn't been told yet)
+fn newly_crossed(side: Option<Side>, last_side: &mut Option<Side>) -> Option<Side> {
+    let previous_side = std::mem::replace(last_side, side);
+    side.filter(|side| previous_side != Some(*side))
 }
 
-pub fn run() -> Result<(), Box<dyn Error>> {
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
     // Load config
     // TODO: make this changeable using a flag
     let config = Config::new(CONFIG_PATH);
 
-    // Connect to BoxRec
-    let mut boxrec = BoxRecAPI::new(&config)?;
-    boxrec.login()?;
-
-    // Connect to Betfair
-    let betfair = BetfairAPI::new()?;
-    // Scrape Betfair
-    let bouts = betfair.get_listed_bouts()?;
-    //println!("{:#?}", bouts);
+    match &args.command {
+        None | Some(Command::Scan) => scan(&config, &args),
+        Some(Command::Daemon) => daemon::run_daemon(config, &args),
+        Some(Command::Tui) => tui::run_tui(config, &args),
+        Some(Command::History { bout, chart }) => {
+            let storage = Storage::open(config.cache_path.as_deref())?;
+            history::show_history(&storage, bout, chart.as_deref())
+        },
+        Some(Command::Backtest { thresholds, kelly_fraction }) => {
+            let storage = Storage::open(config.cache_path.as_deref())?;
+            let thresholds = if thresholds.is_empty() {
+                vec![config.get_notify_threshold()]
+            } else {
+                thresholds.clone()
+            };
+            backtest::run_backtest(&storage, &thresholds, *kelly_fraction)
+        },
+        Some(Command::Calibration { bins, csv, chart }) => {
+            let storage = Storage::open(config.cache_path.as_deref())?;
+            calibration::show_calibration(&storage, *bins, csv.as_deref(), chart.as_deref())
+        },
+        Some(Command::Bets(command)) => {
+            let mut storage = Storage::open(config.cache_path.as_deref())?;
+            match command {
+                BetsCommand::Place { bout, selection, stake, odds, bookmaker } =>
+                    ledger::place_bet(&mut storage, bout, selection, *stake, odds, bookmaker.as_deref()),
+                BetsCommand::Open => ledger::show_open_bets(&storage),
+                BetsCommand::Pnl { chart } =>
+                    ledger::show_pnl(&storage, config.get_starting_bankroll(), chart.as_deref()),
+            }
+        },
+    }
+}
 
-    // Create Boxer HashMap (runtime cache/index of Boxers by name)
-    let mut boxers: HashMap<String, Boxer> = HashMap::new();
-    let mut bout_metadata: Vec<BoutMetadata> = Vec::new();
+// Everything a scan needs that's worth keeping alive between scans, when running as a daemon
+struct Session {
+    _lock: Option<CacheLock>,
+    // Drives BoxRec's and the odds sources' clients, which are async so lookups can overlap
+    runtime: Runtime,
+    storage: Storage,
+    boxrec: BoxRecAPI,
+    sources: OddsSources,
+}
 
-    // Load disk cache before running
-    if let Some(cache_path) = &config.cache_path {
-        // Check for and create cache folder
-        match fs::metadata(cache_path) {
-            // If Ok(), it exists
-            // If it's a file, get scared, otherwise, we have a folder!
-            Ok(md) => if md.is_file() {
-                return Err("Cache path points to an existing file".into());
+impl Session {
+    // Returns None if another run is already using the cache
+    fn open(config: &Config, args: &Args) -> Result<Option<Session>, Box<dyn Error>> {
+        // Check for and create cache folder, then make sure nobody else is using it
+        // This needs to happen before connecting to BoxRec, as the rate limiter keeps its state in there
+        let lock = match &config.cache_path {
+            Some(cache_path) => {
+                create_cache_dir(cache_path)?;
+                match CacheLock::acquire(cache_path, args.wait)? {
+                    Some(lock) => Some(lock),
+                    None => return Ok(None),
+                }
             },
-            Err(e) => match e.kind() {
-                // If the folder doesn't exist yet, try and make it
-                ErrorKind::NotFound => fs::create_dir_all(cache_path)?,
-                // If there's another error be spooked
-                _ => return Err(e.into()),
-            }
+            None => None,
         };
 
-        // Read pre-existing boxers cache if present and in a good format
-        match fs::read_to_string(format!("{}/boxers.yml", cache_path)) {
-            Ok(serialised) => serde_yaml::from_str::<Vec<Boxer>>(&serialised)?
-                    .into_iter()
-                    .for_each(|b| { boxers.insert(b.get_name(), b); }),
-            Err(err) => match err.kind() {
-                ErrorKind::NotFound => {},
-                _ => return Err(err.into()),
+        // Open database, importing any old YAML caches
+        let storage = Storage::open(config.cache_path.as_deref())?;
+
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()?;
+
+        // Connect to BoxRec
+        let boxrec = BoxRecAPI::new(config, args.refresh)?;
+        runtime.block_on(boxrec.login())?;
+
+        // Connect to Betfair (and any other bookmakers)
+        let sources = OddsSources::new(&config.get_odds_sources()?)?;
+
+        Ok(Some(Session {
+            _lock: lock,
+            runtime,
+            storage,
+            boxrec,
+            sources,
+        }))
+    }
+}
+
+// Told how a scan's getting on, so something like the TUI can show its progress
+trait ScanObserver {
+    // Every unsettled bout, as it stood before this scan
+    fn bouts_found(&mut self, _results: &[ScanResult]) {}
+    fn checking(&mut self, _bout_id: i64) {}
+    fn checked(&mut self, _result: &ScanResult) {}
+}
+
+// For when nobody's watching
+impl ScanObserver for () {}
+
+fn scan(config: &Config, args: &Args) -> Result<(), Box<dyn Error>> {
+    match Session::open(config, args)? {
+        Some(mut session) => scan_once(config, args, &mut session, &mut ()),
+        None => {
+            errln!("Another run is already using the cache, exiting");
+            Ok(())
+        },
+    }
+}
+
+fn scan_once(config: &Config, args: &Args, session: &mut Session, observer: &mut dyn ScanObserver) -> Result<(), Box<dyn Error>> {
+    let Session { runtime, storage, boxrec, sources, .. } = session;
+    // Records written to stdout get it to themselves, so anything else that would go there goes to stderr instead
+    let records_to_stdout = args.format.is_some() && args.output.is_none();
+    // Alerts are delivered outside the runtime, as sinks like the webhook use blocking clients,
+    // which panic if they're used inside it
+    let (boxers, bout_metadata) = runtime.block_on(list_bouts(config, storage, sources))?;
+    // Arbitrages don't need BoxRec, and are unlikely to last while it's looked up, so they go out straight away
+    notify::deliver_pending(config, storage, records_to_stdout)?;
+    let mut results = runtime.block_on(scan_concurrently(config, storage, boxrec, boxers, bout_metadata, observer))?;
+
+    notify::deliver_pending(config, storage, records_to_stdout)?;
+    output::print_summary(&mut results, args.sort_by_edge, records_to_stdout)?;
+    if let Some(cache_path) = &config.cache_path {
+        // The report's a nice to have, so don't let it take the run down with it
+        if let Err(err) = report::write_report(cache_path, storage, &results, config.get_notify_threshold()) {
+            errln!("Failed to write report (Error: {})", err);
+        }
+    }
+    if let Some(format) = args.format {
+        output::write_results(&results, format, output::records_writer(args.output.as_deref())?)?;
+    }
+
+    ledger::settle_bets(storage)?;
+    if let Some(days) = config.prune_settled_after_days {
+        storage.prune_settled(days)?;
+    }
+    Ok(())
+}
+
+// Step -1: Gets every bout listed now, saving them along with their latest prices and any arbitrages
+// Returns the runtime index of Boxers by name, and every unsettled bout
+async fn list_bouts(config: &Config, storage: &Storage, sources: &OddsSources) -> Result<(HashMap<String, Boxer>, Vec<BoutMetadata>), Box<dyn Error>> {
+    // Scrape Betfair (and co.) while the runtime index of Boxers by name, and bouts we've seen before, are loaded
+    let (bouts, known) = futures::join!(
+        sources.get_listed_bouts(),
+        async { Ok::<_, Box<dyn Error>>((storage.load_boxers()?, storage.load_bouts()?)) },
+    );
+    let bouts = bouts?;
+    //println!("{:#?}", bouts);
+    let (boxers, mut bout_metadata): (HashMap<String, Boxer>, Vec<BoutMetadata>) = known?;
+
+    for bout in bouts.into_iter() {
+        let bout = BoutMetadata::new(bout);
+        let index = match bout_metadata.iter().position(|bm| bm == &bout) {
+            Some(index) => {
+                let existing = &mut bout_metadata[index];
+                // Kept the way round we first saw it, as that's how its sides and model output are stored
+                let listed = if odds::same_name(&existing.bout.fighter_one, &bout.bout.fighter_one) {
+                    bout.bout
+                } else {
+                    bout.bout.swapped()
+                };
+                // Keep the latest prices, and remember they've moved so the bout gets checked again
+                if existing.bout.odds != listed.odds {
+                    existing.bout.odds = listed.odds;
+                    existing.odds_moved = true;
+                }
+                existing.bout.exchange = listed.exchange;
+                existing.bout.sources = listed.sources;
+                existing.bout.market_link = listed.market_link;
+                existing.bout.betfair_odds = listed.betfair_odds;
+                index
             },
-        };
-        //println!("Read from disk cache into runtime index:\n{:#?}", boxers);
-
-        // Read pre-existing bouts cache if present and in a good format
-        match fs::read_to_string(format!("{}/bouts.yml", cache_path)) {
-            Ok(serialised) => bout_metadata = serde_yaml::from_str::<Vec<BoutMetadata>>(&serialised)?,
-            Err(err) => match err.kind() {
-                ErrorKind::NotFound => {},
-                _ => return Err(err.into()),
+            None => {
+                bout_metadata.push(bout);
+                bout_metadata.len() - 1
             },
         };
+        // Save new bouts straight away so they have an ID for their odds snapshot
+        let metadata = &mut bout_metadata[index];
+        metadata.last_seen = now();
+        let arbitrage = check_arbitrage(metadata, config);
+        let bout_id = storage.save_bout(metadata)?;
+        // Bouts only other books list have no Betfair prices to record
+        if let Some(odds) = &metadata.bout.betfair_odds {
+            storage.record_odds(bout_id, odds)?;
+        }
+        if let Some(alert) = arbitrage {
+            if let Err(err) = storage.record_notification(bout_id, &alert) {
+                errln!("Failed to record notification (Error: {})", err);
+            }
+        }
+    }
+    Ok((boxers, bout_metadata))
+}
+
+// Returns a result for every unsettled bout checked
+async fn scan_concurrently(config: &Config,
+                           storage: &mut Storage,
+                           boxrec: &BoxRecAPI,
+                           mut boxers: HashMap<String, Boxer>,
+                           mut bout_metadata: Vec<BoutMetadata>,
+                           observer: &mut dyn ScanObserver) -> Result<Vec<ScanResult>, Box<dyn Error>> {
+    let mut found = Vec::new();
+    for metadata in &bout_metadata {
+        found.push(ScanResult::new(
+            metadata,
+            boxers.get(&metadata.bout.fighter_one),
+            boxers.get(&metadata.bout.fighter_two),
+            storage.latest_model_output(metadata.id.unwrap(), BOXREC_SCORE_MODEL)?.as_ref(),
+        ));
+    }
+    observer.bouts_found(&found);
+
+    // Each step below works through the bouts at once, so anything already in the page cache isn't stuck
+    // waiting behind the lookups that need BoxRec
+
+    // Step 0: Settle bouts that have been and gone
+    let settled: Vec<bool> = stream::iter(bout_metadata.iter_mut())
+        .map(|metadata| {
+            let boxers = &boxers;
+            async move {
+                // Bouts we can't check keep their status, so will be picked up next run
+                !boxrec.budget_exhausted().await && lifecycle::settle(boxrec, boxers, metadata).await
+            }
+        })
+        .buffered(CONCURRENT_LOOKUPS)
+        .collect()
+        .await;
+
+    // Step 1: Get boxers, looking each one up only once even if they're in more than one bout
+    let mut wanted: Vec<String> = Vec::new();
+    for (metadata, settled) in bout_metadata.iter().zip(&settled) {
+        if *settled || metadata.status != BoutStatus::MissingBoxers { continue; }
+        for name in &[&metadata.bout.fighter_one, &metadata.bout.fighter_two] {
+            if !boxers.contains_key(*name) && !wanted.contains(name) {
+                wanted.push(name.to_string());
+            }
+        }
+    }
+    let looked_up: Vec<(String, Option<Boxer>)> = stream::iter(wanted)
+        .map(|name| async move {
+            let boxer = if boxrec.budget_exhausted().await {
+                None
+            } else {
+                Boxer::new_by_name(boxrec, &name).await
+            };
+            (name, boxer)
+        })
+        .buffered(CONCURRENT_LOOKUPS)
+        .collect()
+        .await;
+    for (name, boxer) in looked_up {
+        // Insert them into the index if present
+        if let Some(boxer) = boxer {
+            boxers.insert(name, boxer);
+        }
     }
 
-    bouts.into_iter()
-        .for_each(|bout| {
-            let bout = BoutMetadata(bout, BoutStatus::MissingBoxers);
-            if !bout_metadata.contains(&bout) { bout_metadata.push(bout); }
-        });
+    // Steps 2 & 3: Get the bouts between boxers and compare them
+    let checker = BoutChecker {
+        thresholds: Thresholds::new(config),
+        boxrec,
+        storage,
+        boxers: &boxers,
+        observer: RefCell::new(observer),
+        budget_warned: Cell::new(false),
+    };
+    let checked: Vec<Result<Option<ScanResult>, Box<dyn Error>>> = stream::iter(bout_metadata.iter_mut().zip(settled))
+        .map(|(metadata, settled)| checker.check(metadata, settled))
+        .buffered(CONCURRENT_LOOKUPS)
+        .collect()
+        .await;
+    let mut results = Vec::new();
+    for result in checked {
+        if let Some(result) = result? {
+            results.push(result);
+        }
+    }
 
-    for BoutMetadata(bout, status) in bout_metadata.iter_mut() {
-        // Step 1: Get boxers
-        if status == &BoutStatus::MissingBoxers {
-            // If we don't have fighter one
-            let mut have_one = true;
-            let mut have_two = true;
-            if !boxers.contains_key(&bout.fighter_one) {
-                // Look them up with BoxRec
-                if let Some(f1) = Boxer::new_by_name(&mut boxrec, &bout.fighter_one) {
-                    // Insert them into the index if present
-                    boxers.insert(bout.fighter_one.to_string(), f1);
-                } else {
-                    have_one = false;
-                }
+    // Save to disk after running
+    storage.save_run(&boxers, &mut bout_metadata)?;
+
+    //config.save()?;
+    Ok(results)
+}
+
+// What checking a bout needs, shared between all the bouts being checked at once
+struct BoutChecker<'a, 'b> {
+    thresholds: Thresholds,
+    boxrec: &'a BoxRecAPI,
+    storage: &'a Storage,
+    boxers: &'a HashMap<String, Boxer>,
+    observer: RefCell<&'a mut (dyn ScanObserver + 'b)>,
+    budget_warned: Cell<bool>,
+}
+
+impl<'a, 'b> BoutChecker<'a, 'b> {
+    // Returns None for bouts that are settled, as they're not part of the summary
+    async fn check(&self, metadata: &mut BoutMetadata, settled: bool) -> Result<Option<ScanResult>, Box<dyn Error>> {
+        // Every bout is saved before we get here, so it will always have an ID
+        let bout_id = metadata.id.unwrap();
+        let (boxrec, storage, boxers) = (self.boxrec, self.storage, self.boxers);
+
+        if settled {
+            self.observer.borrow_mut().checked(&self.result(metadata)?);
+            return Ok(None);
+        }
+        // Anything we haven't got to yet keeps its status, so will be picked up next run
+        if boxrec.budget_exhausted().await {
+            if !self.budget_warned.replace(true) {
+                errln!("Daily BoxRec request budget spent, deferring remaining bouts to the next run");
             }
-            // If we don't have fighter two, same process as one
-            if !boxers.contains_key(&bout.fighter_two) {
-                if let Some(f2) = Boxer::new_by_name(&mut boxrec, &bout.fighter_two) {
-                    boxers.insert(bout.fighter_two.to_string(), f2);
-                } else {
-                    have_two = false;
-                }
+            if metadata.status.is_settled() {
+                return Ok(None);
+            }
+            let mut result = self.result(metadata)?;
+            result.skip_reason = Some(String::from("BoxRec request budget spent"));
+            self.observer.borrow_mut().checked(&result);
+            return Ok(Some(result));
+        }
+
+        self.observer.borrow_mut().checking(bout_id);
+        let BoutMetadata { bout, status, event, edge_side, lay_side, odds_moved, .. } = metadata;
+        // Why we couldn't get any further with this bout, if we didn't
+        let mut skip_reason = None;
+
+        // Step 1: Get boxers, which have been looked up already
+        if status == &BoutStatus::MissingBoxers {
+            let have_one = boxers.contains_key(&bout.fighter_one);
+            let have_two = boxers.contains_key(&bout.fighter_two);
+            if have_one && have_two {
+                status.next();
+            } else {
+                let missing = match (have_one, have_two) {
+                    (false, false) => format!("{} & {}", bout.fighter_one, bout.fighter_two),
+                    (false, true) => bout.fighter_one.clone(),
+                    _ => bout.fighter_two.clone(),
+                };
+                skip_reason = Some(format!("Couldn't find {} on BoxRec", missing));
             }
-            if have_one && have_two { status.next(); }
         }
 
         // Step 2: Get bout between boxers
@@ -270,48 +965,57 @@ pub fn run() -> Result<(), Box<dyn Error>> {
             let fighter_one = boxers.get(&bout.fighter_one).unwrap();
             let fighter_two = boxers.get(&bout.fighter_two).unwrap();
 
-            let boxrec_odds = match fighter_one.get_bout_scores(&mut boxrec, &fighter_two) {
-                Ok(m) => m,
+            match fighter_one.get_bout_scores(boxrec, fighter_two).await {
+                Ok((bout_event, boxrec_odds)) => {
+                    *event = Some(bout_event);
+                    status.next();
+
+                    storage.record_model_output(bout_id, BOXREC_SCORE_MODEL, &boxrec_odds)?;
+                    *status = compare_and_notify(&boxrec_odds, bout, &self.thresholds, edge_side, lay_side, storage, bout_id);
+                },
                 Err(err) => {
-                    eprintln!("Failed to get bout between {} & {} (Error: {})",
-                              fighter_one.get_name(),
-                              fighter_two.get_name(),
-                              err);
-                    continue;
+                    errln!("Failed to get bout between {} & {} (Error: {})",
+                           fighter_one.get_name(),
+                           fighter_two.get_name(),
+                           err);
+                    skip_reason = Some(err.to_string());
                 },
-            };
-            status.next();
-
-            compare_and_notify(&boxrec_odds, bout, &config.get_notify_threshold());
+            }
+        } else if *odds_moved && (status == &BoutStatus::Checked || status == &BoutStatus::Announced) {
+            // Step 3: Prices have moved since we last compared, so compare again using our last model output
+            let fighter_one = boxers.get(&bout.fighter_one).unwrap();
+            let fighter_two = boxers.get(&bout.fighter_two).unwrap();
+            if let Some(output) = storage.latest_model_output(bout_id, BOXREC_SCORE_MODEL)? {
+                let matchup = Matchup::from_output(fighter_one, fighter_two, &output);
+                let new_status = compare_and_notify(&matchup, bout, &self.thresholds, edge_side, lay_side, storage, bout_id);
+                // Once announced, always announced
+                *status = (*status).max(new_status);
+            }
         }
+
+        let mut result = self.result(metadata)?;
+        result.skip_reason = skip_reason;
+        // For the report, so only from profiles that are already in the page cache
+        result.record_one = self.record(&metadata.bout.fighter_one);
+        result.record_two = self.record(&metadata.bout.fighter_two);
+        self.observer.borrow_mut().checked(&result);
+        Ok(Some(result))
     }
 
-    // Save disk cache after running
-    if let Some(cache_path) = &config.cache_path {
-        let mut boxers_file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(format!("{}/boxers.yml", cache_path))?;
-        boxers_file.write(
-            serde_yaml::to_string(
-                &boxers.values()
-                    .collect::<Vec<_>>()
-            )?.as_bytes()
-        )?;
-
-        let mut bouts_file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(format!("{}/bouts.yml", cache_path))?;
-        bouts_file.write(
-            serde_yaml::to_string(
-                &bout_metadata
-            )?.as_bytes()
-        )?;
+    // A boxer's wins, losses and draws, or None if we don't know who they are or haven't got their profile cached
+    fn record(&self, name: &str) -> Option<String> {
+        let boxer = self.boxers.get(name)?;
+        let fights = self.boxrec.cached_fight_history(boxer.get_id())?;
+        let count = |result: &str| fights.iter().filter(|fight| fight.result == result).count();
+        Some(format!("{}-{}-{}", count("W"), count("L"), count("D")))
     }
 
-    //config.save()?;
-    Ok(())
+    fn result(&self, metadata: &BoutMetadata) -> Result<ScanResult, Box<dyn Error>> {
+        Ok(ScanResult::new(
+            metadata,
+            self.boxers.get(&metadata.bout.fighter_one),
+            self.boxers.get(&metadata.bout.fighter_two),
+            self.storage.latest_model_output(metadata.id.unwrap(), BOXREC_SCORE_MODEL)?.as_ref(),
+        ))
+    }
 }
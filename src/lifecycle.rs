@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+
+use crate::boxer::Boxer;
+use crate::boxrec::{BoxRecAPI, Settlement, Winner};
+use crate::storage::now;
+use crate::{BoutMetadata, BoutStatus};
+
+// How long after the event date we'll keep looking for a result before giving up on a bout
+const RESULT_GRACE_DAYS: i64 = 7;
+// How long a bout can go unlisted on Betfair before we give up on it, if we don't know when it's on
+const UNSEEN_EXPIRY_DAYS: i64 = 14;
+
+// Moves bouts which have been and gone on to Completed, Cancelled or Expired
+// Returns true if the bout is settled, meaning there's nothing more to do with it
+pub async fn settle(boxrec: &BoxRecAPI, boxers: &HashMap<String, Boxer>, metadata: &mut BoutMetadata) -> bool {
+    if metadata.status.is_settled() { return true; }
+
+    let today = Utc::now().date_naive();
+    let unseen_for_too_long = now() - metadata.last_seen > UNSEEN_EXPIRY_DAYS * 86_400;
+
+    let settled_as = match (&metadata.status, &metadata.event) {
+        (BoutStatus::Checked, Some(event)) | (BoutStatus::Announced, Some(event)) => {
+            // Nothing to do until the fight's happened
+            if let Some(date) = event.date {
+                if date >= today { return false; }
+            }
+            let fighter_one = match boxers.get(&metadata.bout.fighter_one) {
+                Some(boxer) => boxer,
+                None => return false,
+            };
+
+            match boxrec.get_bout_settlement(fighter_one.get_id(), event).await {
+                Ok(Some(Settlement::Completed(result))) => {
+                    metadata.result = Some(result);
+                    Some(BoutStatus::Completed)
+                },
+                Ok(Some(Settlement::Cancelled)) => Some(BoutStatus::Cancelled),
+                // No result yet, give BoxRec a while to catch up before giving up
+                Ok(None) => match event.date {
+                    Some(date) if today - date > Duration::days(RESULT_GRACE_DAYS) => Some(BoutStatus::Expired),
+                    None if unseen_for_too_long => Some(BoutStatus::Expired),
+                    _ => None,
+                },
+                Err(err) => {
+                    errln!("Failed to check result of {} vs. {} (Error: {})",
+                           metadata.bout.fighter_one,
+                           metadata.bout.fighter_two,
+                           err);
+                    None
+                },
+            }
+        },
+        // We never found the bout on BoxRec, so all we can go on is Betfair no longer listing it
+        _ => if unseen_for_too_long { Some(BoutStatus::Expired) } else { None },
+    };
+
+    match settled_as {
+        Some(status) => {
+            metadata.status = status;
+            metadata.settled_at = Some(now());
+            errln!("{} vs. {}: {}{}",
+                   metadata.bout.fighter_one,
+                   metadata.bout.fighter_two,
+                   status,
+                   describe_result(metadata));
+            true
+        },
+        None => false,
+    }
+}
+
+fn describe_result(metadata: &BoutMetadata) -> String {
+    match &metadata.result {
+        Some(result) => {
+            let winner = match result.winner {
+                Winner::FighterOne => format!("{} won", metadata.bout.fighter_one),
+                Winner::FighterTwo => format!("{} won", metadata.bout.fighter_two),
+                Winner::Draw => String::from("draw"),
+                Winner::NoContest => String::from("no contest"),
+            };
+            match &result.method {
+                Some(method) => format!(" ({} by {})", winner, method),
+                None => format!(" ({})", winner),
+            }
+        },
+        None => String::new(),
+    }
+}
@@ -1,7 +1,11 @@
 use std::process::exit;
 
+use structopt::StructOpt;
+
+use boxrec_tool::Args;
+
 fn main() {
-    if let Err(err) = boxrec_tool::run() {
+    if let Err(err) = boxrec_tool::run(Args::from_args()) {
         eprintln!("Error while running: {}", err);
         exit(2);
     }
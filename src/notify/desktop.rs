@@ -0,0 +1,35 @@
+use std::error::Error;
+use std::process::Command;
+
+use super::{Alert, Notifier};
+
+// What desktop notifications are recorded as
+pub const SINK_NAME: &str = "desktop";
+const TITLE: &str = "BoxRec Heuristic";
+
+// Pops up a notification using whatever the OS provides, rather than pulling in a GUI crate
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let body = alert.message();
+        let mut command = if cfg!(target_os = "macos") {
+            let mut command = Command::new("osascript");
+            command.arg("-e").arg(format!("display notification {:?} with title {:?}", body, TITLE));
+            command
+        } else {
+            // libnotify's notify-send, which most Linux desktops have
+            let mut command = Command::new("notify-send");
+            command.arg(TITLE).arg(&body);
+            command
+        };
+
+        let program = command.get_program().to_string_lossy().to_string();
+        let status = command.status()
+            .map_err(|err| format!("couldn't run {} ({})", program, err))?;
+        if !status.success() {
+            return Err(format!("{} exited with {}", program, status).into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+
+use super::{Alert, Notifier, Position};
+use crate::arbitrage::Leg;
+use crate::format::{escape, format_timestamp};
+
+// What deliveries by email are recorded as
+pub const SINK_NAME: &str = "email";
+const DEFAULT_PORT: u16 = 587;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub host: String,
+    port: Option<u16>,
+    // Upgrade the connection with STARTTLS, only turn this off for a relay on your own machine/network
+    starttls: Option<bool>,
+    username: Option<String>,
+    password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl EmailConfig {
+    pub fn get_port(&self) -> u16 {
+        match &self.port {
+            Some(port) => *port,
+            None => DEFAULT_PORT,
+        }
+    }
+
+    pub fn get_starttls(&self) -> bool {
+        match &self.starttls {
+            Some(starttls) => *starttls,
+            None => true,
+        }
+    }
+}
+
+pub struct EmailNotifier {
+    mailer: SmtpTransport,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailNotifier {
+    pub fn new(config: &EmailConfig) -> Result<EmailNotifier, Box<dyn Error>> {
+        if config.to.is_empty() {
+            return Err("No email recipients configured".into());
+        }
+
+        let mut builder = if config.get_starttls() {
+            SmtpTransport::starttls_relay(&config.host)?
+        } else {
+            SmtpTransport::builder_dangerous(&config.host)
+        }.port(config.get_port());
+        // Same as BoxRec, a password without a username is ignored
+        if let Some(username) = &config.username {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                config.password.clone().unwrap_or_default(),
+            ));
+        }
+
+        Ok(EmailNotifier {
+            mailer: builder.build(),
+            from: config.from.parse()?,
+            to: config.to.iter()
+                .map(|to| to.parse())
+                .collect::<Result<Vec<Mailbox>, _>>()?,
+        })
+    }
+
+    // Sends every alert from the run in one email, rather than one email each
+    pub fn send_digest(&self, alerts: &[&Alert]) -> Result<(), Box<dyn Error>> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(format!("BoxRec Heuristic: {} new alert(s)", alerts.len()));
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message.multipart(MultiPart::alternative_plain_html(
+            render_plain(alerts),
+            render_html(alerts),
+        ))?;
+
+        self.mailer.send(&message)?;
+        Ok(())
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        self.send_digest(&[alert])
+    }
+
+    // One email for the lot, so they all make it or none do
+    fn send_all(&self, alerts: &[&Alert]) -> Vec<bool> {
+        let delivered = match self.send_digest(alerts) {
+            Ok(()) => true,
+            Err(err) => {
+                errln!("Failed to send email digest (Error: {})", err);
+                false
+            },
+        };
+        vec![delivered; alerts.len()]
+    }
+}
+
+fn render_plain(alerts: &[&Alert]) -> String {
+    let mut body = String::from("We might be onto something chief!\n\n");
+    for alert in alerts {
+        body.push_str(&format!("{}\n  {}\n\n", format_timestamp(alert.created_at), alert.message()));
+    }
+    body
+}
+
+fn render_html(alerts: &[&Alert]) -> String {
+    let mut body = String::from(
+        "<html><body style=\"font-family: sans-serif\">\n<p>We might be onto something chief!</p>\n\
+         <table cellpadding=\"4\" style=\"border-collapse: collapse\">\n\
+         <tr><th align=\"left\">Fighter</th><th align=\"left\">Against</th><th align=\"right\">BoxRec</th>\
+         <th align=\"right\">Betfair</th><th align=\"right\">Edge</th><th align=\"left\">Bet</th><th align=\"left\"></th></tr>\n"
+    );
+    for alert in alerts {
+        body.push_str(&format!(
+            "<tr><td><b>{}</b></td><td>{}</td><td align=\"right\">{}</td><td align=\"right\">{}</td>\
+             <td align=\"right\" style=\"color: #2ca02c\">{:+.1}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&alert.fighter),
+            escape(&alert.opponent),
+            alert.win_percent.map(|percent| format!("{:.1}%", percent)).unwrap_or_else(|| String::from("-")),
+            match (alert.odds, alert.position) {
+                (Some(odds), _) => format!("{} ({:.1}%)", escape(&odds.as_frac()), odds.as_percent()),
+                (None, Position::Arbitrage { book_percent, .. }) => format!("Book {:.1}%", book_percent),
+                (None, _) => String::from("-"),
+            },
+            alert.edge(),
+            match alert.position {
+                Position::Back => String::from("Back"),
+                Position::Lay { stake, liability } => format!("Lay {:.2} (liability {:.2})", stake, liability),
+                Position::Arbitrage { outlay, profit, .. } => format!("Every outcome, {:.2} profit on {:.2}:{}",
+                                                                     profit,
+                                                                     outlay,
+                                                                     alert.legs.iter().map(render_leg).collect::<String>()),
+            },
+            if alert.warning { "Both boxers' BoxRec scores are below the safe threshold" } else { "" },
+        ));
+    }
+    body.push_str("</table>\n</body></html>\n");
+    body
+}
+
+// One of an arbitrage's bets, on its own line in the Bet cell
+fn render_leg(leg: &Leg) -> String {
+    format!("<br>{:.2} on {} at {}{}",
+            leg.stake,
+            escape(&leg.selection),
+            escape(&leg.odds.as_frac()),
+            leg.source.as_ref().map(|source| format!(" on {}", escape(source))).unwrap_or_default())
+}
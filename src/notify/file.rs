@@ -0,0 +1,31 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use super::{Alert, Notifier};
+use crate::format::format_timestamp;
+
+// What deliveries to a file are recorded as
+pub const SINK_NAME: &str = "file";
+
+// Appends a line per alert, handy for tailing or grepping later
+pub struct FileNotifier {
+    path: String,
+}
+
+impl FileNotifier {
+    pub fn new(path: &str) -> FileNotifier {
+        FileNotifier { path: path.to_string() }
+    }
+}
+
+impl Notifier for FileNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}\t{:+.1}\t{}", format_timestamp(alert.created_at), alert.edge(), alert.message())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+use std::error::Error;
+
+use chrono::{Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::odds::Odds;
+use crate::arbitrage::Leg;
+use crate::boxer::SCORE_WARNING;
+use crate::storage::{now, Storage};
+use crate::Config;
+
+pub mod desktop;
+pub mod email;
+pub mod file;
+pub mod stdout;
+pub mod webhook;
+
+use desktop::DesktopNotifier;
+use email::{EmailConfig, EmailNotifier};
+use file::FileNotifier;
+use stdout::StdoutNotifier;
+use webhook::{WebhookConfig, WebhookNotifier};
+
+// Undelivered alerts older than this are dropped rather than sent, so turning a sink on (or fixing
+// one that's been failing) doesn't flood it with stale alerts
+const MAX_DELIVERY_AGE_HOURS: i64 = 24;
+
+// A fighter whose chances BoxRec rates more highly than Betfair does, or (for lays) less highly
+// Arbitrages are about the whole bout, so the fighter is just fighter one
+pub struct Alert {
+    pub fighter: String,
+    pub opponent: String,
+    // Our chance of the fighter winning, 0-100. None for arbitrages, which don't depend on BoxRec
+    pub win_percent: Option<f32>,
+    // The back price, or the lay price for lays. None for arbitrages, whose prices are in their legs
+    pub odds: Option<Odds>,
+    // Where the odds are on offer, not recorded before schema version 9 (or for arbitrages, see their legs)
+    pub source: Option<String>,
+    pub position: Position,
+    // Both boxers' scores are below SCORE_WARNING, so the percentage is less trustworthy
+    pub warning: bool,
+    // BoxRec's scores for the fighter and their opponent, not recorded before schema version 7
+    pub scores: Option<(f32, f32)>,
+    pub created_at: i64,
+    // Every bet to place, only for arbitrages
+    pub legs: Vec<Leg>,
+}
+
+// Which way an alert suggests betting on the fighter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Back,
+    // Against them on the exchange, accepting a backer's stake that costs the liability if they win
+    Lay { stake: f32, liability: f32 },
+    // On every outcome, at prices which make a profit whoever wins, i.e. whose implied chances add up to under 100%
+    Arbitrage { book_percent: f32, outlay: f32, profit: f32 },
+}
+
+impl Position {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Position::Back => "back",
+            Position::Lay { .. } => "lay",
+            Position::Arbitrage { .. } => "arbitrage",
+        }
+    }
+}
+
+impl Alert {
+    // How much better our chance of winning is than Betfair's for the position, in percentage points
+    // For arbitrages, the profit as a percentage of the outlay
+    pub fn edge(&self) -> f32 {
+        match self.position {
+            Position::Back => self.win_percent() - self.odds().as_percent(),
+            Position::Lay { .. } => self.odds().as_percent() - self.win_percent(),
+            Position::Arbitrage { outlay, profit, .. } => profit / outlay * 100f32,
+        }
+    }
+
+    // Backs and lays always have our chance and the price, it's only arbitrages that go without
+    fn win_percent(&self) -> f32 {
+        self.win_percent.unwrap()
+    }
+
+    fn odds(&self) -> Odds {
+        self.odds.unwrap()
+    }
+
+    pub fn message(&self) -> String {
+        let warning = if self.warning { "[WARNING: both boxers have a BoxRec score below the safe threshold] " } else { "" };
+        match self.position {
+            Position::Back => format!("{}BoxRec shows {} as having a {:.1}% chance of winning against {}, and yet the betting odds are {} ({:.1}%){}",
+                                      warning,
+                                      self.fighter,
+                                      self.win_percent(),
+                                      self.opponent,
+                                      self.odds().as_frac(),
+                                      self.odds().as_percent(),
+                                      self.source.as_ref().map(|source| format!(" on {}", source)).unwrap_or_default()),
+            Position::Lay { stake, liability } => format!("{}BoxRec only gives {} a {:.1}% chance of winning against {}, and yet they can be laid at {} ({:.1}%), \
+                                                          lay {:.2} for a liability of {:.2}",
+                                                          warning,
+                                                          self.fighter,
+                                                          self.win_percent(),
+                                                          self.opponent,
+                                                          self.odds().as_frac(),
+                                                          self.odds().as_percent(),
+                                                          stake,
+                                                          liability),
+            Position::Arbitrage { outlay, profit, .. } => format!("Backing every outcome of {} vs. {} makes {:.2} on {:.2} ({:.1}%) whoever wins: {}",
+                                                              self.fighter,
+                                                              self.opponent,
+                                                              profit,
+                                                              outlay,
+                                                              self.edge(),
+                                                              self.legs.iter()
+                                                                  .map(|leg| format!("{:.2} on {} at {}{}",
+                                                                                     leg.stake,
+                                                                                     leg.selection,
+                                                                                     leg.odds.as_frac(),
+                                                                                     leg.source.as_ref().map(|source| format!(" on {}", source)).unwrap_or_default()))
+                                                                  .collect::<Vec<_>>()
+                                                                  .join(", ")),
+        }
+    }
+}
+
+// Somewhere alerts can be sent
+pub trait Notifier {
+    fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>>;
+
+    // Sends a run's worth of alerts, returning whether each one made it
+    // By default they're sent one at a time, sinks that batch them up (i.e. email) override this
+    fn send_all(&self, alerts: &[&Alert]) -> Vec<bool> {
+        alerts.iter()
+            .map(|alert| match self.send(alert) {
+                Ok(()) => true,
+                Err(err) => {
+                    errln!("Failed to send alert for {} (Error: {})", alert.fighter, err);
+                    false
+                },
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkKind {
+    Stdout,
+    File { path: String },
+    Webhook(WebhookConfig),
+    Email(EmailConfig),
+    Desktop,
+}
+
+impl SinkKind {
+    fn default_name(&self) -> &'static str {
+        match self {
+            SinkKind::Stdout => stdout::SINK_NAME,
+            SinkKind::File { .. } => file::SINK_NAME,
+            SinkKind::Webhook(_) => webhook::SINK_NAME,
+            SinkKind::Email(_) => email::SINK_NAME,
+            SinkKind::Desktop => desktop::SINK_NAME,
+        }
+    }
+
+    fn build(&self, stdout_taken: bool) -> Result<Box<dyn Notifier>, Box<dyn Error>> {
+        Ok(match self {
+            SinkKind::Stdout => Box::new(StdoutNotifier { to_stderr: stdout_taken }),
+            SinkKind::File { path } => Box::new(FileNotifier::new(path)),
+            SinkKind::Webhook(config) => Box::new(WebhookNotifier::new(config)?),
+            SinkKind::Email(config) => Box::new(EmailNotifier::new(config)?),
+            SinkKind::Desktop => Box::new(DesktopNotifier),
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SinkConfig {
+    // What deliveries are recorded as, only needed to tell apart two sinks of the same type
+    name: Option<String>,
+    #[serde(flatten)]
+    kind: SinkKind,
+    // Alerts are only sent if the edge is at least this many percentage points
+    min_edge: Option<f32>,
+    // Alerts are only sent if the boxers' average BoxRec score is at least this
+    min_score: Option<f32>,
+    // Local time range to hold alerts back during, e.g. "23:00-07:00"
+    quiet_hours: Option<String>,
+}
+
+impl SinkConfig {
+    // Sinks from before there was a list of them, which don't have any filters
+    pub fn unfiltered(kind: SinkKind) -> SinkConfig {
+        SinkConfig {
+            name: None,
+            kind,
+            min_edge: None,
+            min_score: None,
+            quiet_hours: None,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        match &self.name {
+            Some(name) => name,
+            None => self.kind.default_name(),
+        }
+    }
+}
+
+struct Sink {
+    name: String,
+    notifier: Box<dyn Notifier>,
+    min_edge: Option<f32>,
+    min_score: Option<f32>,
+    quiet_hours: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl Sink {
+    fn new(config: &SinkConfig, stdout_taken: bool) -> Result<Sink, Box<dyn Error>> {
+        Ok(Sink {
+            name: config.get_name().to_string(),
+            notifier: config.kind.build(stdout_taken)?,
+            min_edge: config.min_edge,
+            min_score: config.min_score,
+            quiet_hours: match &config.quiet_hours {
+                Some(hours) => Some(parse_quiet_hours(hours)?),
+                None => None,
+            },
+        })
+    }
+
+    fn is_quiet(&self, time: NaiveTime) -> bool {
+        match self.quiet_hours {
+            Some((start, end)) if start <= end => time >= start && time < end,
+            // Runs over midnight
+            Some((start, end)) => time >= start || time < end,
+            None => false,
+        }
+    }
+
+    fn wants(&self, alert: &Alert) -> bool {
+        // A sure thing doesn't depend on the model, so nothing here applies
+        if let Position::Arbitrage { .. } = alert.position {
+            return true;
+        }
+        if let Some(min_edge) = self.min_edge {
+            if alert.edge() < min_edge {
+                return false;
+            }
+        }
+        if let Some(min_score) = self.min_score {
+            let passes = match alert.scores {
+                Some((fighter, opponent)) => (fighter + opponent) / 2f32 >= min_score,
+                // Older alerts only have the warning flag, which means the average is under SCORE_WARNING
+                None => !(alert.warning && min_score >= SCORE_WARNING),
+            };
+            if !passes {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Fans alerts out to every configured sink, applying each one's filters
+pub struct Dispatcher {
+    sinks: Vec<Sink>,
+}
+
+impl Dispatcher {
+    // If stdout's taken (i.e. by records), alerts for it are printed to stderr instead
+    pub fn new(configs: &[SinkConfig], stdout_taken: bool) -> Dispatcher {
+        let sinks = configs.iter()
+            .filter_map(|config| match Sink::new(config, stdout_taken) {
+                Ok(sink) => Some(sink),
+                Err(err) => {
+                    errln!("Failed to set up {} notifications, skipping them (Error: {})", config.get_name(), err);
+                    None
+                },
+            })
+            .collect();
+        Dispatcher { sinks }
+    }
+
+    // Sends any alerts that haven't made it to each sink yet, including ones from earlier runs that
+    // failed to send or were held back by quiet hours
+    pub fn deliver_pending(&self, storage: &Storage) -> Result<(), Box<dyn Error>> {
+        let since = now() - MAX_DELIVERY_AGE_HOURS * 60 * 60;
+        let time = Local::now().time();
+
+        for sink in &self.sinks {
+            if sink.is_quiet(time) {
+                continue;
+            }
+            // Filtered out alerts are left undelivered rather than recorded, so they age out on their own
+            let (ids, alerts): (Vec<i64>, Vec<Alert>) = storage.undelivered_alerts(&sink.name, since)?
+                .into_iter()
+                .filter(|(_, alert)| sink.wants(alert))
+                .unzip();
+            if alerts.is_empty() {
+                continue;
+            }
+
+            let delivered = sink.notifier.send_all(&alerts.iter().collect::<Vec<_>>());
+            for (notification_id, delivered) in ids.iter().zip(delivered) {
+                // Anything that didn't make it is tried again next run
+                if delivered {
+                    storage.record_delivery(*notification_id, &sink.name)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn deliver_pending(config: &Config, storage: &Storage, stdout_taken: bool) -> Result<(), Box<dyn Error>> {
+    Dispatcher::new(&config.get_notifiers(), stdout_taken).deliver_pending(storage)
+}
+
+// Parses "HH:MM-HH:MM", the start and end of the quiet period
+fn parse_quiet_hours(s: &str) -> Result<(NaiveTime, NaiveTime), Box<dyn Error>> {
+    let parts: Vec<&str> = s.split('-').collect();
+    if parts.len() != 2 {
+        return Err(format!("quiet_hours should look like 23:00-07:00, got {}", s).into());
+    }
+    Ok((
+        NaiveTime::parse_from_str(parts[0].trim(), "%H:%M")?,
+        NaiveTime::parse_from_str(parts[1].trim(), "%H:%M")?,
+    ))
+}
@@ -0,0 +1,23 @@
+use std::error::Error;
+
+use super::{Alert, Notifier};
+
+// What deliveries to the terminal are recorded as
+pub const SINK_NAME: &str = "stdout";
+
+pub struct StdoutNotifier {
+    // When records are going to stdout, so alerts don't get mixed up with them
+    pub to_stderr: bool,
+}
+
+impl Notifier for StdoutNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let message = format!("---\nWe might be onto something chief!\n{}\n---", alert.message());
+        if self.to_stderr {
+            errln!("{}", message);
+        } else {
+            outln!("{}", message);
+        }
+        Ok(())
+    }
+}
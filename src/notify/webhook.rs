@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+
+use super::{Alert, Notifier, Position};
+
+// What deliveries to the webhook are recorded as
+pub const SINK_NAME: &str = "webhook";
+// Slack's incoming webhooks take this as is
+const DEFAULT_TEMPLATE: &str = r#"{"text": "{{message}}"}"#;
+const DEFAULT_RETRIES: u32 = 3;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    headers: Option<BTreeMap<String, String>>,
+    // JSON body to send, with {{placeholders}} filled in from the alert
+    template: Option<String>,
+    retries: Option<u32>,
+}
+
+impl WebhookConfig {
+    pub fn get_template(&self) -> &str {
+        match &self.template {
+            Some(template) => template,
+            None => DEFAULT_TEMPLATE,
+        }
+    }
+
+    pub fn get_retries(&self) -> u32 {
+        match &self.retries {
+            Some(retries) => *retries,
+            None => DEFAULT_RETRIES,
+        }
+    }
+}
+
+pub struct WebhookNotifier {
+    reqwest_client: Client,
+    config: WebhookConfig,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookConfig) -> Result<WebhookNotifier, Box<dyn Error>> {
+        Ok(WebhookNotifier {
+            reqwest_client: Client::builder()
+                .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+                .build()?,
+            config: config.clone(),
+        })
+    }
+
+}
+
+impl Notifier for WebhookNotifier {
+    // Posts the alert, retrying with a backoff if the endpoint's having a bad time
+    fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let body = render_template(self.config.get_template(), alert)?;
+
+        let mut attempt = 0;
+        loop {
+            let mut request = self.reqwest_client
+                .post(&self.config.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(headers) = &self.config.headers {
+                for (name, value) in headers {
+                    request = request.header(name.as_str(), value.as_str());
+                }
+            }
+
+            let error = match request.send() {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    // Anything else the endpoint doesn't like won't get better by asking again
+                    if status.is_client_error() && status.as_u16() != 429 {
+                        return Err(format!("webhook responded {}", status).into());
+                    }
+                    format!("webhook responded {}", status)
+                },
+                Err(err) => err.to_string(),
+            };
+
+            if attempt >= self.config.get_retries() {
+                return Err(error.into());
+            }
+            // 1s, 2s, 4s...
+            let backoff = Duration::from_secs(1 << attempt.min(6));
+            errln!("Webhook failed (Error: {}), retrying in {}s", error, backoff.as_secs());
+            thread::sleep(backoff);
+            attempt += 1;
+        }
+    }
+}
+
+// Fills the alert into the template, escaping everything so it's safe inside a JSON string
+// Numbers are substituted without quotes, so can be used as JSON numbers or inside strings
+fn render_template(template: &str, alert: &Alert) -> Result<String, Box<dyn Error>> {
+    let values = [
+        ("message", alert.message()),
+        ("fighter", alert.fighter.clone()),
+        ("opponent", alert.opponent.clone()),
+        // Both 0 for arbitrages, which don't depend on BoxRec
+        ("win_percent", format!("{:.1}", alert.win_percent.unwrap_or_default())),
+        ("betfair_percent", format!("{:.1}", alert.odds.map(|odds| odds.as_percent()).unwrap_or_default())),
+        ("edge", format!("{:.1}", alert.edge())),
+        ("odds", alert.odds.map(|odds| odds.as_frac()).unwrap_or_default()),
+        ("warning", alert.warning.to_string()),
+        ("position", alert.position.key().to_string()),
+        ("source", alert.source.clone().unwrap_or_default()),
+        // Both 0 unless it's a lay
+        ("lay_stake", format!("{:.2}", match alert.position { Position::Lay { stake, .. } => stake, _ => 0f32 })),
+        ("liability", format!("{:.2}", match alert.position { Position::Lay { liability, .. } => liability, _ => 0f32 })),
+        // All 0 unless it's an arbitrage
+        ("book_percent", format!("{:.1}", match alert.position { Position::Arbitrage { book_percent, .. } => book_percent, _ => 0f32 })),
+        ("outlay", format!("{:.2}", match alert.position { Position::Arbitrage { outlay, .. } => outlay, _ => 0f32 })),
+        ("profit", format!("{:.2}", match alert.position { Position::Arbitrage { profit, .. } => profit, _ => 0f32 })),
+    ];
+
+    let mut rendered = String::from(template);
+    for (name, value) in &values {
+        rendered = rendered.replace(&format!("{{{{{}}}}}", name), &json_escape(value));
+    }
+
+    // Catch a broken template here, rather than have the endpoint reject every alert
+    if let Err(err) = serde_json::from_str::<serde_json::Value>(&rendered) {
+        return Err(format!("webhook template isn't valid JSON once filled in ({})", err).into());
+    }
+    Ok(rendered)
+}
+
+fn json_escape(s: &str) -> String {
+    let quoted = serde_json::to_string(s).unwrap_or_else(|_| String::from("\"\""));
+    // Drop the quotes serde_json puts round strings
+    quoted[1..quoted.len() - 1].to_string()
+}
@@ -0,0 +1,412 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use serde::{Deserialize, Serialize};
+
+use crate::betfair::exchange::{ExchangeAPI, ExchangeConfig};
+use crate::betfair::BetfairSportsbook;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Bout {
+    pub fighter_one: String,
+    pub fighter_two: String,
+    // The price to back each outcome at
+    pub odds: BoutOdds,
+    // Only known when the bout was found on the exchange, and never stored
+    #[serde(default)]
+    pub exchange: Option<ExchangeOdds>,
+    // Which source each of the odds came from, only known for bouts listed this run
+    #[serde(default)]
+    pub sources: Option<BoutSources>,
+    // The bout's own market page, if the source has one, and only known for bouts listed this run
+    #[serde(default)]
+    pub market_link: Option<String>,
+    // Betfair's own prices, from the first Betfair source listing the bout. These are what's recorded,
+    // so the history and backtests are always against one book rather than whichever was best each scan
+    #[serde(default)]
+    pub betfair_odds: Option<BoutOdds>,
+}
+
+impl Bout {
+    // The same bout with the fighters the other way round, for when books disagree on who goes first
+    pub fn swapped(self) -> Bout {
+        Bout {
+            fighter_one: self.fighter_two,
+            fighter_two: self.fighter_one,
+            odds: BoutOdds {
+                one_wins: self.odds.two_wins,
+                draw: self.odds.draw,
+                two_wins: self.odds.one_wins,
+            },
+            exchange: self.exchange.map(|exchange| ExchangeOdds {
+                one: exchange.two,
+                draw: exchange.draw,
+                two: exchange.one,
+            }),
+            sources: self.sources.map(|sources| BoutSources {
+                one_wins: sources.two_wins,
+                draw: sources.draw,
+                two_wins: sources.one_wins,
+            }),
+            market_link: self.market_link,
+            betfair_odds: self.betfair_odds.map(|odds| BoutOdds {
+                one_wins: odds.two_wins,
+                draw: odds.draw,
+                two_wins: odds.one_wins,
+            }),
+        }
+    }
+
+    // Takes whichever of the two prices is longer for each outcome
+    fn take_best(&mut self, other: Bout) {
+        let other_sources = other.sources.unwrap_or_default();
+        let sources = self.sources.get_or_insert_with(BoutSources::default);
+        for (odds, source, other_odds, other_source) in [
+            (&mut self.odds.one_wins, &mut sources.one_wins, other.odds.one_wins, other_sources.one_wins),
+            (&mut self.odds.draw, &mut sources.draw, other.odds.draw, other_sources.draw),
+            (&mut self.odds.two_wins, &mut sources.two_wins, other.odds.two_wins, other_sources.two_wins),
+        ] {
+            if other_odds.as_decimal() > odds.as_decimal() {
+                *odds = other_odds;
+                *source = other_source;
+            }
+        }
+        // Only one source can be the exchange
+        if self.exchange.is_none() {
+            self.exchange = other.exchange;
+        }
+        if self.market_link.is_none() {
+            self.market_link = other.market_link;
+        }
+        if self.betfair_odds.is_none() {
+            self.betfair_odds = other.betfair_odds;
+        }
+    }
+}
+
+impl Display for Bout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} vs. {} ({})", self.fighter_one, self.fighter_two, self.odds)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct BoutOdds {
+    pub one_wins: Odds,
+    pub draw: Odds,
+    pub two_wins: Odds,
+}
+
+impl Display for BoutOdds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "1: {}, X: {}, 2: {}", self.one_wins, self.draw, self.two_wins)
+    }
+}
+
+// What's on offer for each outcome on the exchange
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ExchangeOdds {
+    pub one: RunnerPrices,
+    pub draw: RunnerPrices,
+    pub two: RunnerPrices,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct BoutSources {
+    pub one_wins: String,
+    pub draw: String,
+    pub two_wins: String,
+}
+
+impl BoutSources {
+    fn all(source: &str) -> BoutSources {
+        BoutSources {
+            one_wins: source.to_string(),
+            draw: source.to_string(),
+            two_wins: source.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct RunnerPrices {
+    // Best prices available, None if nobody's offering any
+    pub back: Option<Odds>,
+    pub lay: Option<Odds>,
+    // How much has been matched on this outcome so far, in the market's currency
+    pub matched: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Odds {
+    top: u32,
+    bottom: u32,
+}
+
+// https://www.aceodds.com/bet-calculator/odds-converter.html
+impl Odds {
+    // Parses the Betfair Sportsbook's odds buttons
+    pub fn from_mangled_string(s: String) -> Result<Odds, Box<dyn Error>> {
+        // Warning: copious jank
+        // The only way to solve this would be to match the exact <span> element that holds the fraction
+        // But its CSS is no different to the parent <a> element, so I end up getting the whole <span> element instead of the inner HTML of it, which would be just the fraction
+        // The arg typically looks something like "\n<span class=\"ui-runner-price ui-924_231809773-28625857 ui-display-fraction-price\">\n8/15\n<", "span>\n"
+
+        // Edge case - 'evens' odds
+        if s.contains("EVS") {
+            Ok(Odds {
+                top: 1,
+                bottom: 1,
+            })
+        } else {
+            // Split on /
+            let mut parts = s.split("/");
+
+            let odds = Odds {
+                top: parts.next()
+                    // Check we actually got something - woo safety!
+                    .ok_or("Top of fraction not found")?
+                    // Reverse split because for the top number it's at the end of the string
+                    .rsplit("\n")
+                    // The number is the first thing as it's always after a \n (see above sample)
+                    .next()
+                    // Unwrapping this option can never fail because a split always returns an iterator of at least one item
+                    .unwrap()
+                    // Make it a u32!
+                    .parse()?,
+
+                bottom: parts.next()
+                    .ok_or("Bottom of fraction not found")?
+                    // Normal split in this case as the number should be the first item before a \n (see above sample)
+                    .split("\n")
+                    .next()
+                    .unwrap()
+                    .parse()?,
+            };
+            // Same as from_frac, a bottom of 0 isn't odds
+            if odds.bottom == 0 {
+                return Err(format!("Odds can't have a bottom of 0, got {}/0", odds.top).into());
+            }
+            Ok(odds)
+        }
+    }
+
+    // Parses odds written out by as_frac, e.g. "8/15"
+    pub fn from_frac(s: &str) -> Result<Odds, Box<dyn Error>> {
+        let mut parts = s.split('/');
+        let odds = Odds {
+            top: parts.next().ok_or("Top of fraction not found")?.trim().parse()?,
+            bottom: parts.next().ok_or("Bottom of fraction not found")?.trim().parse()?,
+        };
+        // Would make the decimal odds infinite (or NaN) and the implied chance 0%
+        if odds.bottom == 0 {
+            return Err(format!("Odds can't have a bottom of 0, got {}", s).into());
+        }
+        Ok(odds)
+    }
+
+    // Parses odds typed in by a user, which can be fractional ("6/4"), decimal ("2.5") or "evens"
+    pub fn from_user(s: &str) -> Result<Odds, Box<dyn Error>> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("evens") || s.eq_ignore_ascii_case("evs") {
+            return Ok(Odds { top: 1, bottom: 1 });
+        }
+        if s.contains('/') {
+            return Odds::from_frac(s);
+        }
+
+        Odds::from_decimal(s.parse()?)
+    }
+
+    // Exchange prices are decimal, e.g. 2.5
+    pub fn from_decimal(decimal: f32) -> Result<Odds, Box<dyn Error>> {
+        // Work in hundredths, then cancel down so 2.5 comes out as 3/2 rather than 150/100
+        let top = ((decimal - 1f32) * 100f32).round() as u32;
+        // Anything that rounds to 1 (or isn't a number) would be 0/1 odds, and lay stakes are divided by the top
+        if !decimal.is_finite() || top == 0 {
+            return Err(format!("Decimal odds must be more than 1, got {}", decimal).into());
+        }
+        let divisor = gcd(top, 100);
+        Ok(Odds {
+            top: top / divisor,
+            bottom: 100 / divisor,
+        })
+    }
+
+    // Used to quote profit
+    pub fn as_frac(&self) -> String {
+        format!("{}/{}", self.top, self.bottom)
+    }
+
+    // Used to show bookie's perceived odds
+    pub fn as_percent(&self) -> f32 {
+        let top = self.top as f32;
+        let bot = self.bottom as f32;
+        100f32 * (bot / (top + bot))
+    }
+
+    // Used to calculate return (profit + stake)
+    pub fn as_decimal(&self) -> f32 {
+        1f32 + self.top as f32 / self.bottom as f32
+    }
+}
+
+impl Display for Odds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_frac())
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a.max(1) } else { gcd(b, a % b) }
+}
+
+// Somewhere bouts are listed with prices, e.g. a bookmaker
+pub trait OddsSource: Send {
+    // What the source goes by in output, which is unique among the configured sources
+    fn name(&self) -> &str;
+    // Whether the prices are Betfair's own, which are what get recorded
+    fn is_betfair(&self) -> bool {
+        false
+    }
+    fn get_listed_bouts(&self) -> LocalBoxFuture<'_, Result<Vec<Bout>, Box<dyn Error>>>;
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SourceConfig {
+    BetfairSportsbook,
+    BetfairExchange(ExchangeConfig),
+    // Bouts written out by something else, for books there's no scraper for
+    File { name: String, path: String },
+}
+
+impl SourceConfig {
+    fn build(&self) -> Result<Box<dyn OddsSource>, Box<dyn Error>> {
+        Ok(match self {
+            SourceConfig::BetfairSportsbook => Box::new(BetfairSportsbook::new()?),
+            SourceConfig::BetfairExchange(exchange_config) => Box::new(ExchangeAPI::new(exchange_config)?),
+            SourceConfig::File { name, path } => Box::new(FileSource { name: name.clone(), path: path.clone() }),
+        })
+    }
+}
+
+// Reads bouts from a JSON file, which is read afresh every scan so whatever writes it can keep it up to date
+// e.g. [{"fighter_one": "...", "fighter_two": "...", "one_wins": "6/4", "draw": "25/1", "two_wins": "1.5"}]
+struct FileSource {
+    name: String,
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct FileBout {
+    fighter_one: String,
+    fighter_two: String,
+    // Anything Odds::from_user takes, i.e. fractional, decimal or evens
+    one_wins: String,
+    draw: String,
+    two_wins: String,
+}
+
+impl OddsSource for FileSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_listed_bouts(&self) -> LocalBoxFuture<'_, Result<Vec<Bout>, Box<dyn Error>>> {
+        async move {
+            let contents = fs::read_to_string(&self.path)
+                .map_err(|err| format!("Couldn't read {} (Error: {})", self.path, err))?;
+            let listed: Vec<FileBout> = serde_json::from_str(&contents)?;
+            let mut bouts = Vec::new();
+            for bout in listed {
+                bouts.push(Bout {
+                    odds: BoutOdds {
+                        one_wins: Odds::from_user(&bout.one_wins)?,
+                        draw: Odds::from_user(&bout.draw)?,
+                        two_wins: Odds::from_user(&bout.two_wins)?,
+                    },
+                    fighter_one: bout.fighter_one,
+                    fighter_two: bout.fighter_two,
+                    exchange: None,
+                    sources: None,
+                    market_link: None,
+                    betfair_odds: None,
+                });
+            }
+            Ok(bouts)
+        }.boxed_local()
+    }
+}
+
+// Every configured source, listed as one
+pub struct OddsSources {
+    sources: Vec<Box<dyn OddsSource>>,
+}
+
+impl OddsSources {
+    pub fn new(configs: &[SourceConfig]) -> Result<OddsSources, Box<dyn Error>> {
+        if configs.is_empty() {
+            return Err("No odds sources configured".into());
+        }
+        let mut sources: Vec<Box<dyn OddsSource>> = Vec::new();
+        for config in configs {
+            let source = config.build()?;
+            if sources.iter().any(|other| other.name() == source.name()) {
+                return Err(format!("There's more than one odds source called {}", source.name()).into());
+            }
+            sources.push(source);
+        }
+        Ok(OddsSources { sources })
+    }
+
+    // Every bout any source lists, at the longest price any of them has for each outcome
+    // A source failing only loses its prices, unless they all fail
+    pub async fn get_listed_bouts(&self) -> Result<Vec<Bout>, Box<dyn Error>> {
+        let listings = future::join_all(self.sources.iter().map(|source| source.get_listed_bouts())).await;
+
+        let mut bouts: Vec<Bout> = Vec::new();
+        let mut first_err = None;
+        let mut failures = 0;
+        for (source, listing) in self.sources.iter().zip(listings) {
+            let listing = match listing {
+                Ok(listing) => listing,
+                Err(err) => {
+                    errln!("Failed to get bouts from {} (Error: {})", source.name(), err);
+                    failures += 1;
+                    first_err.get_or_insert(err);
+                    continue;
+                },
+            };
+            for mut bout in listing {
+                bout.sources = Some(BoutSources::all(source.name()));
+                if source.is_betfair() {
+                    bout.betfair_odds = Some(bout.odds);
+                }
+                match bouts.iter_mut().find(|known| same_bout(known, &bout)) {
+                    Some(known) if same_name(&known.fighter_one, &bout.fighter_one) => known.take_best(bout),
+                    Some(known) => known.take_best(bout.swapped()),
+                    None => bouts.push(bout),
+                }
+            }
+        }
+        if failures == self.sources.len() {
+            return Err(first_err.unwrap());
+        }
+
+        errln!("Checking bouts");
+        Ok(bouts)
+    }
+}
+
+// Books don't always agree on capitalisation, spacing or who's listed first
+pub fn same_bout(a: &Bout, b: &Bout) -> bool {
+    (same_name(&a.fighter_one, &b.fighter_one) && same_name(&a.fighter_two, &b.fighter_two))
+        || (same_name(&a.fighter_one, &b.fighter_two) && same_name(&a.fighter_two, &b.fighter_one))
+}
+
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.split_whitespace().map(str::to_lowercase).eq(b.split_whitespace().map(str::to_lowercase))
+}
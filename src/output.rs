@@ -0,0 +1,205 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::odds::Odds;
+use crate::arbitrage::book_percent;
+use crate::boxer::Boxer;
+use crate::console;
+use crate::storage::{status_key, ModelOutput};
+use crate::BoutMetadata;
+
+// Machine readable formats scan results can be written out in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    JsonLines,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jsonl" | "json" => Ok(OutputFormat::JsonLines),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Unknown output format \"{}\", expected jsonl or csv", s)),
+        }
+    }
+}
+
+// Everything we know about a bout after checking it, flat so it fits in a CSV row
+// Percentages are 0-100, and edges are our chance minus Betfair's
+#[derive(Clone, Serialize)]
+pub struct ScanResult {
+    pub bout_id: i64,
+    pub fighter_one: String,
+    pub fighter_two: String,
+    pub boxrec_id_one: Option<u32>,
+    pub boxrec_id_two: Option<u32>,
+    pub score_one: Option<f32>,
+    pub score_two: Option<f32>,
+    pub model_percent_one: Option<f32>,
+    pub model_percent_two: Option<f32>,
+    pub odds_one: String,
+    pub odds_draw: String,
+    pub odds_two: String,
+    pub implied_percent_one: f32,
+    pub implied_percent_draw: f32,
+    pub implied_percent_two: f32,
+    // Which source each price came from, if the bout was listed this run
+    pub source_one: Option<String>,
+    pub source_draw: Option<String>,
+    pub source_two: Option<String>,
+    // The implied percentages added up, under 100 is an arbitrage
+    pub book_percent: f32,
+    pub edge_one: Option<f32>,
+    pub edge_two: Option<f32>,
+    pub status: &'static str,
+    // BoxRec's page for the bout, once we've found it
+    pub event_link: Option<String>,
+    // The bout's market, if it was listed this run by a source that has a page for it
+    pub market_link: Option<String>,
+    // Each boxer's wins, losses and draws as on their BoxRec profile, e.g. 12-1-0
+    pub record_one: Option<String>,
+    pub record_two: Option<String>,
+    pub warning: bool,
+    // Why the bout couldn't be fully checked this run, e.g. a fighter couldn't be found
+    pub skip_reason: Option<String>,
+}
+
+impl ScanResult {
+    pub fn new(metadata: &BoutMetadata,
+               fighter_one: Option<&Boxer>,
+               fighter_two: Option<&Boxer>,
+               output: Option<&ModelOutput>) -> ScanResult {
+        let odds = &metadata.bout.odds;
+        let model_percent_one = output.map(|output| output.win_percent_one);
+        let model_percent_two = model_percent_one.map(|percent| 100f32 - percent);
+        ScanResult {
+            // Every bout is saved before it's checked, so will always have an ID
+            bout_id: metadata.id.unwrap_or_default(),
+            fighter_one: metadata.bout.fighter_one.clone(),
+            fighter_two: metadata.bout.fighter_two.clone(),
+            boxrec_id_one: fighter_one.map(|boxer| *boxer.get_id()),
+            boxrec_id_two: fighter_two.map(|boxer| *boxer.get_id()),
+            score_one: output.and_then(|output| output.scores).map(|(one, _)| one),
+            score_two: output.and_then(|output| output.scores).map(|(_, two)| two),
+            model_percent_one,
+            model_percent_two,
+            odds_one: odds.one_wins.as_frac(),
+            odds_draw: odds.draw.as_frac(),
+            odds_two: odds.two_wins.as_frac(),
+            implied_percent_one: odds.one_wins.as_percent(),
+            implied_percent_draw: odds.draw.as_percent(),
+            implied_percent_two: odds.two_wins.as_percent(),
+            source_one: metadata.bout.sources.as_ref().map(|sources| sources.one_wins.clone()),
+            source_draw: metadata.bout.sources.as_ref().map(|sources| sources.draw.clone()),
+            source_two: metadata.bout.sources.as_ref().map(|sources| sources.two_wins.clone()),
+            book_percent: book_percent(odds),
+            edge_one: edge(model_percent_one, &odds.one_wins),
+            edge_two: edge(model_percent_two, &odds.two_wins),
+            status: status_key(&metadata.status),
+            event_link: metadata.event.as_ref().map(|event| format!("https://boxrec.com{}", event.link)),
+            market_link: metadata.bout.market_link.clone(),
+            record_one: None,
+            record_two: None,
+            warning: output.is_some_and(|output| output.warning),
+            skip_reason: None,
+        }
+    }
+
+    // The bigger of the two fighters' edges
+    pub fn best_edge(&self) -> Option<f32> {
+        match (self.edge_one, self.edge_two) {
+            (Some(one), Some(two)) => Some(one.max(two)),
+            (one, two) => one.or(two),
+        }
+    }
+}
+
+fn edge(model_percent: Option<f32>, odds: &Odds) -> Option<f32> {
+    model_percent.map(|percent| percent - odds.as_percent())
+}
+
+// Prints a table of every bout checked this run, so bouts that didn't cross the threshold aren't silent.
+// It goes to stderr when records are going to stdout, so the two don't get mixed up
+pub fn print_summary(results: &mut [ScanResult], sort_by_edge: bool, to_stderr: bool) -> Result<(), Box<dyn Error>> {
+    if results.is_empty() { return Ok(()); }
+    if sort_by_edge {
+        // Biggest edge first, bouts we couldn't work an edge out for at the bottom
+        results.sort_by(|a, b| match (a.best_edge(), b.best_edge()) {
+            (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+    }
+
+    let mut out: Box<dyn Write> = if to_stderr { console::stderr() } else { console::stdout() };
+    writeln!(out)?;
+    writeln!(out, "{:<45}  {:>13}  {:>13}  {:>6}  {:<17}  Notes",
+             "Bout", "1 model/mkt", "2 model/mkt", "Edge", "Status")?;
+    for result in results.iter() {
+        let mut notes = match (&result.skip_reason, result.warning) {
+            (Some(reason), _) => reason.clone(),
+            (None, true) => String::from("Low BoxRec scores, take with a pinch of salt"),
+            (None, false) => String::new(),
+        };
+        // Only for prices that were listed this run, older ones may not be on offer any more
+        if result.book_percent < 100f32 && result.source_one.is_some() {
+            if !notes.is_empty() {
+                notes.push_str("; ");
+            }
+            notes.push_str(&format!("Arbitrage, prices add up to {:.1}%", result.book_percent));
+        }
+        writeln!(out, "{:<45}  {:>13}  {:>13}  {:>6}  {:<17}  {}",
+                 format!("{} vs. {}", result.fighter_one, result.fighter_two),
+                 model_vs_market(result.model_percent_one, result.implied_percent_one),
+                 model_vs_market(result.model_percent_two, result.implied_percent_two),
+                 result.best_edge().map(|edge| format!("{:+.1}", edge)).unwrap_or_else(|| String::from("-")),
+                 result.status,
+                 notes)?;
+    }
+    Ok(())
+}
+
+fn model_vs_market(model_percent: Option<f32>, implied_percent: f32) -> String {
+    match model_percent {
+        Some(model_percent) => format!("{:.1}/{:.1}", model_percent, implied_percent),
+        None => format!("-/{:.1}", implied_percent),
+    }
+}
+
+// Where records go, the given file or stdout if there isn't one
+pub fn records_writer(path: Option<&str>) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    Ok(match path {
+        Some(path) => Box::new(File::create(path)?),
+        None => console::stdout(),
+    })
+}
+
+pub fn write_results(results: &[ScanResult], format: OutputFormat, writer: Box<dyn Write>) -> Result<(), Box<dyn Error>> {
+    match format {
+        OutputFormat::JsonLines => {
+            let mut writer = writer;
+            for result in results {
+                serde_json::to_writer(&mut writer, result)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        },
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for result in results {
+                writer.serialize(result)?;
+            }
+            writer.flush()?;
+        },
+    }
+    Ok(())
+}
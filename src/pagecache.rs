@@ -0,0 +1,120 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::time::{Duration, SystemTime};
+
+use crate::Config;
+use crate::cache::atomic_write;
+
+// The different kinds of BoxRec page we download, each goes stale at a different rate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageKind {
+    Search,
+    Profile,
+    Event,
+}
+
+// Disk-backed cache of downloaded pages, keyed by URL
+// Each page is stored in its own file, with the URL on the first line so hash collisions can be spotted
+pub struct PageCache {
+    dir: Option<String>,
+    refresh: bool,
+    search_ttl: Duration,
+    profile_ttl: Duration,
+    event_ttl: Duration,
+}
+
+impl PageCache {
+    pub fn new(config: &Config, refresh: bool) -> PageCache {
+        let dir = config.cache_path.as_ref()
+            .map(|cache_path| format!("{}/pages", cache_path));
+        if let Some(dir) = &dir {
+            if let Err(err) = fs::create_dir_all(dir) {
+                errln!("Failed to create page cache folder, pages won't be cached (Error: {})", err);
+                return PageCache::disabled();
+            }
+        }
+        PageCache {
+            dir,
+            refresh,
+            search_ttl: hours(config.get_search_ttl_hours()),
+            profile_ttl: hours(config.get_profile_ttl_hours()),
+            event_ttl: hours(config.get_event_ttl_hours()),
+        }
+    }
+
+    fn disabled() -> PageCache {
+        PageCache {
+            dir: None,
+            refresh: false,
+            search_ttl: Duration::from_secs(0),
+            profile_ttl: Duration::from_secs(0),
+            event_ttl: Duration::from_secs(0),
+        }
+    }
+
+    fn ttl(&self, kind: PageKind) -> Duration {
+        match kind {
+            PageKind::Search => self.search_ttl,
+            PageKind::Profile => self.profile_ttl,
+            PageKind::Event => self.event_ttl,
+        }
+    }
+
+    fn path_for(&self, url: &str) -> Option<String> {
+        self.dir.as_ref().map(|dir| format!("{}/{:016x}.html", dir, fnv1a(url)))
+    }
+
+    // Returns the cached page if we have one that hasn't expired yet
+    pub fn get(&self, url: &str, kind: PageKind) -> Option<String> {
+        // --refresh means we always go to the network, though we'll still save what we get back
+        if self.refresh { return None; }
+        self.peek(url, kind)
+    }
+
+    // Like get, but even with --refresh, for pages that are only nice to have and aren't worth a request
+    pub fn peek(&self, url: &str, kind: PageKind) -> Option<String> {
+        let path = self.path_for(url)?;
+
+        let age = fs::metadata(&path).ok()?
+            .modified().ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age > self.ttl(kind) { return None; }
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let mut parts = contents.splitn(2, '\n');
+                // Make sure this is actually the page we asked for
+                if parts.next() == Some(url) {
+                    parts.next().map(String::from)
+                } else {
+                    None
+                }
+            },
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    errln!("Failed to read cached page for {} (Error: {})", url, err);
+                }
+                None
+            },
+        }
+    }
+
+    pub fn put(&self, url: &str, page: &str) {
+        if let Some(path) = self.path_for(url) {
+            if let Err(err) = atomic_write(&path, format!("{}\n{}", url, page).as_bytes()) {
+                errln!("Failed to cache page for {} (Error: {})", url, err);
+            }
+        }
+    }
+}
+
+fn hours(n: u64) -> Duration {
+    Duration::from_secs(n * 60 * 60)
+}
+
+// Stable across builds (unlike std's DefaultHasher), which matters as the file names outlive the binary
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
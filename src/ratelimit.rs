@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::delay_for;
+
+use crate::Config;
+use crate::cache::{read_versioned, write_versioned, Migration};
+
+// The most we'll slow ourselves down by after BoxRec starts pushing back
+const MAX_SLOWDOWN: f32 = 8f32;
+// Each successful request brings the slowdown this much closer to normal speed
+const SLOWDOWN_RECOVERY: f32 = 0.9;
+
+// Version 1 only added the versioned envelope, the state itself is unchanged
+const STATE_MIGRATIONS: &[Migration] = &[Ok];
+
+// What gets persisted between runs, so the daily budget survives restarts
+#[derive(Serialize, Deserialize)]
+struct BudgetState {
+    // Days since the UNIX epoch (UTC) that `used` applies to
+    day: u64,
+    used: u32,
+    slowdown: f32,
+}
+
+impl BudgetState {
+    fn fresh() -> BudgetState {
+        BudgetState {
+            day: today(),
+            used: 0,
+            slowdown: 1f32,
+        }
+    }
+}
+
+// Token bucket limiter with a daily request budget on top
+// Tokens refill at `per_minute` (divided by the current slowdown), and every request also waits
+// at least `min_gap` (multiplied by the current slowdown) after the previous one
+pub struct RateLimiter {
+    per_minute: u32,
+    tokens: f32,
+    last_refill: Instant,
+    min_gap: Duration,
+    last_sent: Option<Instant>,
+    daily_budget: u32,
+    state: BudgetState,
+    state_path: Option<String>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> RateLimiter {
+        let state_path = config.cache_path.as_ref()
+            .map(|cache_path| format!("{}/ratelimit.yml", cache_path));
+        let state = match &state_path {
+            Some(path) => read_versioned(path, STATE_MIGRATIONS).unwrap_or_else(BudgetState::fresh),
+            None => BudgetState::fresh(),
+        };
+        let per_minute = config.get_requests_per_minute().max(1);
+
+        let mut limiter = RateLimiter {
+            per_minute,
+            // Start with a full bucket
+            tokens: per_minute as f32,
+            last_refill: Instant::now(),
+            min_gap: Duration::from_millis(config.get_request_delay()),
+            last_sent: None,
+            daily_budget: config.get_daily_request_budget(),
+            state,
+            state_path,
+        };
+        limiter.roll_over_day();
+        limiter
+    }
+
+    // Waits until a request can be sent, or errors if today's budget has been spent
+    // Callers share the limiter behind a lock held across this, so requests queue up in turn
+    pub async fn acquire(&mut self) -> Result<(), Box<dyn Error>> {
+        self.roll_over_day();
+        if self.is_exhausted() {
+            return Err(format!("Daily BoxRec request budget of {} has been spent", self.daily_budget).into());
+        }
+
+        self.pace().await;
+        self.state.used += 1;
+        self.save();
+        Ok(())
+    }
+
+    // Waits for a turn like acquire, but without counting towards (or being stopped by) the daily budget
+    pub async fn pace(&mut self) {
+        // Wait for a token if the bucket is empty
+        self.refill();
+        if self.tokens < 1f32 {
+            let wait = (1f32 - self.tokens) / self.tokens_per_second();
+            delay_for(Duration::from_secs_f32(wait)).await;
+            self.refill();
+        }
+
+        // Then make sure we're not sending requests back to back
+        if let Some(last_sent) = self.last_sent {
+            let gap = self.min_gap.mul_f32(self.state.slowdown);
+            let elapsed = last_sent.elapsed();
+            if elapsed < gap {
+                delay_for(gap - elapsed).await;
+            }
+        }
+
+        self.tokens = (self.tokens - 1f32).max(0f32);
+        self.last_sent = Some(Instant::now());
+    }
+
+    // Called when BoxRec prompts for a reCAPTCHA or responds with 429 Too Many Requests
+    pub fn throttled(&mut self) {
+        self.state.slowdown = (self.state.slowdown * 2f32).min(MAX_SLOWDOWN);
+        self.tokens = 0f32;
+        self.last_refill = Instant::now();
+        errln!("BoxRec is throttling us, slowing requests down by {}x", self.state.slowdown);
+        self.save();
+    }
+
+    // Called when a request goes through without any pushback
+    pub fn succeeded(&mut self) {
+        if self.state.slowdown > 1f32 {
+            self.state.slowdown = (self.state.slowdown * SLOWDOWN_RECOVERY).max(1f32);
+            self.save();
+        }
+    }
+
+    // Counts from a previous day don't apply, even before anything's rolled them over (e.g. a daemon left running)
+    pub fn is_exhausted(&self) -> bool {
+        self.state.day == today() && self.state.used >= self.daily_budget
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.daily_budget.saturating_sub(self.state.used)
+    }
+
+    fn tokens_per_second(&self) -> f32 {
+        self.per_minute as f32 / 60f32 / self.state.slowdown
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * self.tokens_per_second()).min(self.per_minute as f32);
+        self.last_refill = now;
+    }
+
+    // The budget is per day, so reset the count once the date changes
+    fn roll_over_day(&mut self) {
+        let today = today();
+        if self.state.day != today {
+            self.state.day = today;
+            self.state.used = 0;
+        }
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.state_path {
+            if let Err(err) = write_versioned(path, STATE_MIGRATIONS, &self.state) {
+                errln!("Failed to save rate limiter state (Error: {})", err);
+            }
+        }
+    }
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0)
+}
@@ -0,0 +1,141 @@
+use std::error::Error;
+
+use chrono::Utc;
+
+use crate::betfair::BETFAIR_BOXING_URL;
+use crate::cache::atomic_write;
+use crate::chart::{Chart, SPARKLINE_HEIGHT, SPARKLINE_WIDTH};
+use crate::format::escape;
+use crate::odds::BoutOdds;
+use crate::output::ScanResult;
+use crate::storage::Storage;
+
+// Same colours as the history chart, for fighter one and fighter two
+const FIGHTER_COLOURS: [&str; 2] = ["#1f77b4", "#d62728"];
+
+const STYLE: &str = "
+body { font-family: sans-serif; background: #f4f4f4; margin: 2em; color: #222; }
+h1 { margin-bottom: 0.2em; }
+.generated { color: #666; margin-bottom: 2em; }
+.cards { display: flex; flex-wrap: wrap; gap: 1em; }
+.card { background: white; border-radius: 6px; padding: 1em; width: 420px; box-shadow: 0 1px 3px rgba(0,0,0,0.2); border-top: 4px solid #ccc; }
+.card.value { border-top-color: #2ca02c; }
+.card h2 { font-size: 1.1em; margin: 0 0 0.5em 0; }
+.status { color: #666; font-size: 0.9em; }
+table { width: 100%; border-collapse: collapse; margin: 0.5em 0; }
+th, td { text-align: left; padding: 2px 4px; }
+td.num, th.num { text-align: right; }
+.positive { color: #2ca02c; font-weight: bold; }
+.negative { color: #999; }
+.notes { color: #b36b00; font-size: 0.9em; }
+.links a { margin-right: 1em; }
+";
+
+// Writes a self-contained HTML page summarising this run's bouts to the cache folder
+pub fn write_report(cache_path: &str, storage: &Storage, results: &[ScanResult], threshold: f32) -> Result<(), Box<dyn Error>> {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>BoxRec Heuristic report</title>\n\
+         <style>{}</style>\n</head>\n<body>\n<h1>BoxRec Heuristic report</h1>\n\
+         <div class=\"generated\">Generated {} UTC, {} bout(s). Bouts where our chances beat Betfair's by more than {}% are highlighted green.</div>\n\
+         <div class=\"cards\">\n",
+        STYLE,
+        Utc::now().format("%Y-%m-%d %H:%M"),
+        results.len(),
+        threshold,
+    );
+
+    for result in results {
+        html.push_str(&render_card(result, &storage.odds_history(result.bout_id)?, threshold));
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+
+    let path = format!("{}/report.html", cache_path);
+    atomic_write(&path, html.as_bytes())?;
+    errln!("Report saved to {}", path);
+    Ok(())
+}
+
+fn render_card(result: &ScanResult, history: &[(i64, BoutOdds)], threshold: f32) -> String {
+    let is_value = result.best_edge().is_some_and(|edge| edge > threshold);
+    let mut card = format!(
+        "<div class=\"card{}\">\n<h2>{} vs. {}</h2>\n<div class=\"status\">{}</div>\n\
+         <table>\n<tr><th>Fighter</th><th class=\"num\">BoxRec ID</th><th class=\"num\">Record</th><th class=\"num\">Score</th>\
+         <th class=\"num\">Model</th><th class=\"num\">Betfair</th><th class=\"num\">Edge</th></tr>\n",
+        if is_value { " value" } else { "" },
+        escape(&result.fighter_one),
+        escape(&result.fighter_two),
+        escape(result.status),
+    );
+
+    let fighters = [
+        (&result.fighter_one, result.boxrec_id_one, &result.record_one, result.score_one, result.model_percent_one,
+         &result.odds_one, result.implied_percent_one, result.edge_one),
+        (&result.fighter_two, result.boxrec_id_two, &result.record_two, result.score_two, result.model_percent_two,
+         &result.odds_two, result.implied_percent_two, result.edge_two),
+    ];
+    for (index, (name, id, record, score, model, odds, implied, edge)) in fighters.iter().enumerate() {
+        card.push_str(&format!(
+            "<tr><td style=\"color: {}\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td class=\"num\">{}</td><td class=\"num\">{} ({:.1}%)</td>{}</tr>\n",
+            FIGHTER_COLOURS[index],
+            escape(name),
+            match id {
+                Some(id) => format!("<a href=\"https://boxrec.com/en/proboxer/{id}\">{id}</a>", id = id),
+                None => String::from("-"),
+            },
+            record.as_deref().map(escape).unwrap_or_else(|| String::from("-")),
+            score.map(|score| format!("{:.3}", score)).unwrap_or_else(|| String::from("-")),
+            model.map(|model| format!("{:.1}%", model)).unwrap_or_else(|| String::from("-")),
+            escape(odds),
+            implied,
+            match edge {
+                Some(edge) => format!(
+                    "<td class=\"num {}\">{:+.1}</td>",
+                    if *edge > threshold { "positive" } else if *edge < 0f32 { "negative" } else { "" },
+                    edge
+                ),
+                None => String::from("<td class=\"num\">-</td>"),
+            },
+        ));
+    }
+    card.push_str(&format!("<tr><td>Draw</td><td></td><td></td><td></td><td></td><td class=\"num\">{} ({:.1}%)</td><td></td></tr>\n</table>\n",
+                           escape(&result.odds_draw),
+                           result.implied_percent_draw));
+
+    if history.len() > 1 {
+        card.push_str(&format!("<div>Betfair's chances over time<br>{}</div>\n", render_sparkline(history)));
+    }
+    match (&result.skip_reason, result.warning) {
+        (Some(reason), _) => card.push_str(&format!("<div class=\"notes\">{}</div>\n", escape(reason))),
+        (None, true) => card.push_str("<div class=\"notes\">Low BoxRec scores, take with a pinch of salt</div>\n"),
+        (None, false) => (),
+    }
+
+    card.push_str("<div class=\"links\">");
+    if let Some(link) = &result.event_link {
+        card.push_str(&format!("<a href=\"{}\">BoxRec bout page</a>", escape(link)));
+    }
+    match &result.market_link {
+        Some(link) => card.push_str(&format!("<a href=\"{}\">Market</a></div>\n</div>\n", escape(link))),
+        // Bouts that weren't listed this run, or only by a source without a page for the bout
+        None => card.push_str(&format!("<a href=\"{}\">Betfair boxing</a></div>\n</div>\n", BETFAIR_BOXING_URL)),
+    }
+    card
+}
+
+// Tiny line chart of each fighter's implied chance of winning, oldest on the left
+fn render_sparkline(history: &[(i64, BoutOdds)]) -> String {
+    let step = 1f32 / (history.len() - 1) as f32;
+    let mut chart = Chart::sparkline(SPARKLINE_WIDTH, SPARKLINE_HEIGHT);
+    for (index, colour) in FIGHTER_COLOURS.iter().enumerate() {
+        let points = history.iter()
+            .enumerate()
+            .map(|(i, (_, odds))| {
+                let percent = if index == 0 { odds.one_wins.as_percent() } else { odds.two_wins.as_percent() };
+                (i as f32 * step, percent / 100f32)
+            })
+            .collect::<Vec<_>>();
+        chart.polyline(&points, colour, 1.5f32, false);
+    }
+    chart.finish()
+}
@@ -0,0 +1,973 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, NO_PARAMS};
+use serde::Deserialize;
+
+use crate::odds::{self, Bout, BoutOdds, Odds};
+use crate::arbitrage::{self, Leg};
+use crate::boxer::{Boxer, Matchup};
+use crate::boxrec::{BoutResult, Event, Winner};
+use crate::cache::{quarantine, read_versioned};
+use crate::ledger::BetOutcome;
+use crate::notify::{Alert, Position};
+use crate::{BoutMetadata, BoutStatus, Side};
+
+// Each entry takes the schema up by one version (index 0 takes it from version 0 to 1, and so on)
+// Don't edit these once they've been released, add another one on the end instead
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema, replacing boxers.yml & bouts.yml
+    "CREATE TABLE boxers (
+        id INTEGER PRIMARY KEY,
+        forename TEXT NOT NULL,
+        surname TEXT NOT NULL
+    );
+    -- The names boxers go by elsewhere (i.e. on Betfair), which don't always match BoxRec
+    CREATE TABLE aliases (
+        alias TEXT PRIMARY KEY,
+        boxer_id INTEGER NOT NULL REFERENCES boxers(id)
+    );
+    CREATE TABLE bouts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        fighter_one TEXT NOT NULL,
+        fighter_two TEXT NOT NULL,
+        one_wins TEXT NOT NULL,
+        draw TEXT NOT NULL,
+        two_wins TEXT NOT NULL,
+        status TEXT NOT NULL,
+        first_seen INTEGER NOT NULL
+    );
+    CREATE TABLE odds_snapshots (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        bout_id INTEGER NOT NULL REFERENCES bouts(id),
+        taken_at INTEGER NOT NULL,
+        one_wins TEXT NOT NULL,
+        draw TEXT NOT NULL,
+        two_wins TEXT NOT NULL
+    );
+    CREATE INDEX odds_snapshots_bout ON odds_snapshots(bout_id, taken_at);
+    CREATE TABLE model_outputs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        bout_id INTEGER NOT NULL REFERENCES bouts(id),
+        model TEXT NOT NULL,
+        computed_at INTEGER NOT NULL,
+        win_percent_one REAL NOT NULL,
+        win_percent_two REAL NOT NULL,
+        warning INTEGER NOT NULL
+    );
+    CREATE TABLE notifications (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        bout_id INTEGER NOT NULL REFERENCES bouts(id),
+        sent_at INTEGER NOT NULL,
+        fighter TEXT NOT NULL,
+        win_percent REAL NOT NULL,
+        odds TEXT NOT NULL,
+        warning INTEGER NOT NULL
+    );",
+    // 2: bout lifecycle, so bouts can be settled once they've happened
+    "ALTER TABLE bouts ADD COLUMN event_link TEXT;
+    ALTER TABLE bouts ADD COLUMN event_date TEXT;
+    ALTER TABLE bouts ADD COLUMN winner TEXT;
+    ALTER TABLE bouts ADD COLUMN method TEXT;
+    ALTER TABLE bouts ADD COLUMN last_seen INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE bouts ADD COLUMN settled_at INTEGER;
+    UPDATE bouts SET last_seen = first_seen;",
+    // 3: bouts are keyed by their fighters rather than fighters and odds
+    // A price change used to create a new bout, so merge any duplicates into the newest
+    "CREATE TEMP TABLE bout_merges AS
+        SELECT bouts.id AS old_id,
+               (SELECT MAX(newest.id) FROM bouts AS newest
+                WHERE newest.fighter_one = bouts.fighter_one
+                  AND newest.fighter_two = bouts.fighter_two
+                  AND newest.settled_at IS NULL) AS new_id
+        FROM bouts WHERE settled_at IS NULL;
+    DELETE FROM bout_merges WHERE old_id = new_id;
+    UPDATE odds_snapshots SET bout_id = (SELECT new_id FROM bout_merges WHERE old_id = bout_id)
+        WHERE bout_id IN (SELECT old_id FROM bout_merges);
+    UPDATE model_outputs SET bout_id = (SELECT new_id FROM bout_merges WHERE old_id = bout_id)
+        WHERE bout_id IN (SELECT old_id FROM bout_merges);
+    UPDATE notifications SET bout_id = (SELECT new_id FROM bout_merges WHERE old_id = bout_id)
+        WHERE bout_id IN (SELECT old_id FROM bout_merges);
+    DELETE FROM bouts WHERE id IN (SELECT old_id FROM bout_merges);
+    DROP TABLE bout_merges;
+    ALTER TABLE bouts ADD COLUMN edge_side TEXT;",
+    // 4: bet ledger
+    "CREATE TABLE bets (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        bout_id INTEGER NOT NULL REFERENCES bouts(id),
+        selection TEXT NOT NULL,
+        stake REAL NOT NULL,
+        odds TEXT NOT NULL,
+        bookmaker TEXT,
+        placed_at INTEGER NOT NULL,
+        outcome TEXT,
+        profit REAL,
+        settled_at INTEGER
+    );
+    CREATE INDEX bets_bout ON bets(bout_id);",
+    // 5: keep BoxRec's scores alongside the percentages worked out from them
+    "ALTER TABLE model_outputs ADD COLUMN score_one REAL;
+    ALTER TABLE model_outputs ADD COLUMN score_two REAL;",
+    // 6: notifications sent on elsewhere (i.e. webhooks), so each is only delivered once
+    "ALTER TABLE notifications ADD COLUMN opponent TEXT;
+    CREATE TABLE deliveries (
+        notification_id INTEGER NOT NULL REFERENCES notifications(id),
+        sink TEXT NOT NULL,
+        delivered_at INTEGER NOT NULL,
+        PRIMARY KEY (notification_id, sink)
+    );
+    -- Alerts were printed as they were found before there were sinks, so they're already delivered to stdout
+    INSERT INTO deliveries (notification_id, sink, delivered_at) SELECT id, 'stdout', sent_at FROM notifications;",
+    // 7: BoxRec's scores behind each notification, so sinks can filter on them
+    "ALTER TABLE notifications ADD COLUMN fighter_score REAL;
+    ALTER TABLE notifications ADD COLUMN opponent_score REAL;",
+    // 8: lays, which are announced separately from backs
+    "ALTER TABLE bouts ADD COLUMN lay_side TEXT;
+    ALTER TABLE notifications ADD COLUMN position TEXT NOT NULL DEFAULT 'back';
+    ALTER TABLE notifications ADD COLUMN lay_stake REAL;
+    ALTER TABLE notifications ADD COLUMN liability REAL;",
+    // 9: where each notification's odds were on offer, now there can be more than one bookmaker
+    "ALTER TABLE notifications ADD COLUMN source TEXT;",
+    // 10: arbitrages, with the bet to place on each outcome
+    "ALTER TABLE bouts ADD COLUMN arbitrage INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE arbitrage_legs (
+        notification_id INTEGER NOT NULL REFERENCES notifications(id),
+        selection TEXT NOT NULL,
+        odds TEXT NOT NULL,
+        source TEXT,
+        stake REAL NOT NULL
+    );",
+    // 11: the exchange's commission on each arbitrage leg, which comes off the profit
+    "ALTER TABLE arbitrage_legs ADD COLUMN commission REAL NOT NULL DEFAULT 0;",
+    // 12: arbitrages get their own book percentage, rather than borrowing the model's columns (and the first leg's odds),
+    // which can be left empty for them. SQLite can't loosen a column in place, so the table's rebuilt
+    "CREATE TABLE new_notifications (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        bout_id INTEGER NOT NULL REFERENCES bouts(id),
+        sent_at INTEGER NOT NULL,
+        fighter TEXT NOT NULL,
+        win_percent REAL,
+        odds TEXT,
+        warning INTEGER NOT NULL,
+        opponent TEXT,
+        fighter_score REAL,
+        opponent_score REAL,
+        position TEXT NOT NULL DEFAULT 'back',
+        lay_stake REAL,
+        liability REAL,
+        source TEXT,
+        book_percent REAL
+    );
+    INSERT INTO new_notifications (id, bout_id, sent_at, fighter, win_percent, odds, warning, opponent, fighter_score,
+                                   opponent_score, position, lay_stake, liability, source, book_percent)
+        SELECT id, bout_id, sent_at, fighter,
+               CASE WHEN position = 'arbitrage' THEN NULL ELSE win_percent END,
+               CASE WHEN position = 'arbitrage' THEN NULL ELSE odds END,
+               warning, opponent, fighter_score, opponent_score, position, lay_stake, liability,
+               CASE WHEN position = 'arbitrage' THEN NULL ELSE source END,
+               CASE WHEN position = 'arbitrage' THEN win_percent END
+        FROM notifications;
+    DROP TABLE notifications;
+    ALTER TABLE new_notifications RENAME TO notifications;",
+];
+
+// The name the BoxRec score model is stored under in model_outputs
+pub const BOXREC_SCORE_MODEL: &str = "boxrec_score";
+
+// How bouts.yml stored bouts, only needed to import it
+#[derive(Deserialize)]
+struct LegacyBoutMetadata(Bout, BoutStatus);
+
+pub struct Storage {
+    conn: Connection,
+}
+
+// Just enough about a bout to show it to the user
+pub struct BoutSummary {
+    pub id: i64,
+    pub fighter_one: String,
+    pub fighter_two: String,
+    pub status: BoutStatus,
+}
+
+// What a model said about a bout, the last time it was run
+pub struct ModelOutput {
+    pub win_percent_one: f32,
+    pub warning: bool,
+    // Only recorded for the BoxRec score model, and not before schema version 5
+    pub scores: Option<(f32, f32)>,
+}
+
+// A bet from the ledger, along with who was fighting
+pub struct Bet {
+    pub id: i64,
+    pub bout_id: i64,
+    pub fighter_one: String,
+    pub fighter_two: String,
+    pub selection: Winner,
+    pub stake: f32,
+    pub odds: Odds,
+    pub bookmaker: Option<String>,
+    pub placed_at: i64,
+    // All None until the bet's settled
+    pub outcome: Option<BetOutcome>,
+    pub profit: Option<f32>,
+    pub settled_at: Option<i64>,
+}
+
+impl Storage {
+    // Opens (or creates) the database in the cache folder, or an in-memory one if caching is turned off
+    // A database that SQLite can't read is quarantined and replaced with a fresh one
+    pub fn open(cache_path: Option<&str>) -> Result<Storage, Box<dyn Error>> {
+        let mut storage = match cache_path {
+            Some(cache_path) => {
+                let db_path = format!("{}/boxrec.db", cache_path);
+                match Storage::open_connection(Connection::open(&db_path)?) {
+                    Err(err) if is_unreadable(err.as_ref()) => {
+                        errln!("Unable to read database (Error: {})", err);
+                        quarantine(&db_path);
+                        Storage::open_connection(Connection::open(&db_path)?)?
+                    },
+                    result => result?,
+                }
+            },
+            None => Storage::open_connection(Connection::open_in_memory()?)?,
+        };
+
+        if let Some(cache_path) = cache_path {
+            storage.import_yaml(cache_path)?;
+        }
+        Ok(storage)
+    }
+
+    fn open_connection(conn: Connection) -> Result<Storage, Box<dyn Error>> {
+        // Foreign keys are off while migrating, so migrations can rebuild tables that other tables refer to
+        conn.pragma_update(None, "foreign_keys", &false)?;
+        let mut storage = Storage { conn };
+        storage.migrate()?;
+        storage.conn.pragma_update(None, "foreign_keys", &true)?;
+        Ok(storage)
+    }
+
+    fn migrate(&mut self) -> Result<(), Box<dyn Error>> {
+        let version = self.conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get::<_, i64>(0))? as usize;
+        if version > MIGRATIONS.len() {
+            return Err(format!("Database is from a newer version of this program (schema version {}, expected at most {})",
+                               version,
+                               MIGRATIONS.len()).into());
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration)?;
+            // Without foreign keys being enforced, make sure the migration hasn't left anything dangling
+            if tx.query_row("PRAGMA foreign_key_check", NO_PARAMS, |_| Ok(())).optional()?.is_some() {
+                return Err(format!("Migrating the database to schema version {} broke a foreign key", index + 1).into());
+            }
+            tx.pragma_update(None, "user_version", &(index as i64 + 1))?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // Moves the old YAML caches into the database, if there are any
+    // The YAML files are renamed afterwards (rather than deleted) so nothing is lost if something goes wrong
+    fn import_yaml(&mut self, cache_path: &str) -> Result<(), Box<dyn Error>> {
+        let boxers_path = format!("{}/boxers.yml", cache_path);
+        let bouts_path = format!("{}/bouts.yml", cache_path);
+
+        // These were written before versioning was introduced, so there aren't any migrations
+        // If either can't be read it's quarantined, and we import whatever we can
+        let boxers = read_versioned::<Vec<Boxer>>(&boxers_path, &[]);
+        let bouts = read_versioned::<Vec<LegacyBoutMetadata>>(&bouts_path, &[]);
+        if boxers.is_none() && bouts.is_none() { return Ok(()); }
+
+        let imported_bouts = bouts.is_some();
+
+        // bouts.yml got another entry whenever a price changed, and didn't mind which way round the fighters were,
+        // so only the newest entry for each bout is kept (like migration 3 does for the database)
+        let mut latest: Vec<LegacyBoutMetadata> = Vec::new();
+        for legacy in bouts.into_iter().flatten() {
+            match latest.iter().position(|kept| odds::same_bout(&kept.0, &legacy.0)) {
+                Some(index) => latest[index] = legacy,
+                None => latest.push(legacy),
+            }
+        }
+
+        errln!("Importing YAML cache into the database");
+        let tx = self.conn.transaction()?;
+        for boxer in boxers.iter().flatten() {
+            save_boxer(&tx, &boxer.get_name(), boxer)?;
+        }
+        for LegacyBoutMetadata(bout, status) in latest {
+            let mut metadata = BoutMetadata::new(bout);
+            metadata.status = status;
+            save_bout(&tx, &mut metadata)?;
+        }
+        tx.commit()?;
+
+        if boxers.is_some() { fs::rename(&boxers_path, format!("{}.imported", boxers_path))?; }
+        if imported_bouts { fs::rename(&bouts_path, format!("{}.imported", bouts_path))?; }
+        Ok(())
+    }
+
+    // Boxers indexed by every name we know them by
+    pub fn load_boxers(&self) -> Result<HashMap<String, Boxer>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT aliases.alias, boxers.id, boxers.forename, boxers.surname
+             FROM aliases JOIN boxers ON aliases.boxer_id = boxers.id"
+        )?;
+        let rows = statement.query_map(NO_PARAMS, |row| {
+            Ok((row.get::<_, String>(0)?, Boxer::new(row.get(1)?, row.get(2)?, row.get(3)?)))
+        })?;
+
+        let mut boxers = HashMap::new();
+        for row in rows {
+            let (alias, boxer) = row?;
+            boxers.insert(alias, boxer);
+        }
+        Ok(boxers)
+    }
+
+    // Only bouts which haven't been settled yet, settled ones are kept for posterity but otherwise left alone
+    pub fn load_bouts(&self) -> Result<Vec<BoutMetadata>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, fighter_one, fighter_two, one_wins, draw, two_wins, status,
+                    event_link, event_date, winner, method, last_seen, settled_at, edge_side, lay_side, arbitrage
+             FROM bouts WHERE settled_at IS NULL ORDER BY id"
+        )?;
+        let mut rows = statement.query(NO_PARAMS)?;
+
+        let mut bouts = Vec::new();
+        while let Some(row) = rows.next()? {
+            let event = match row.get::<_, Option<String>>(7)? {
+                Some(link) => Some(Event {
+                    link,
+                    date: match row.get::<_, Option<String>>(8)? {
+                        Some(date) => Some(NaiveDate::parse_from_str(&date, "%Y-%m-%d")?),
+                        None => None,
+                    },
+                }),
+                None => None,
+            };
+            let result = match row.get::<_, Option<String>>(9)? {
+                Some(winner) => Some(BoutResult {
+                    winner: parse_winner(&winner)?,
+                    method: row.get(10)?,
+                }),
+                None => None,
+            };
+
+            bouts.push(BoutMetadata {
+                id: Some(row.get(0)?),
+                bout: Bout {
+                    fighter_one: row.get(1)?,
+                    fighter_two: row.get(2)?,
+                    odds: BoutOdds {
+                        one_wins: Odds::from_frac(&row.get::<_, String>(3)?)?,
+                        draw: Odds::from_frac(&row.get::<_, String>(4)?)?,
+                        two_wins: Odds::from_frac(&row.get::<_, String>(5)?)?,
+                    },
+                    exchange: None,
+                    sources: None,
+                    market_link: None,
+                    betfair_odds: None,
+                },
+                status: parse_status(&row.get::<_, String>(6)?)?,
+                event,
+                result,
+                last_seen: row.get(11)?,
+                settled_at: row.get(12)?,
+                edge_side: match row.get::<_, Option<String>>(13)? {
+                    Some(side) => Some(parse_side(&side)?),
+                    None => None,
+                },
+                lay_side: match row.get::<_, Option<String>>(14)? {
+                    Some(side) => Some(parse_side(&side)?),
+                    None => None,
+                },
+                arbitrage: row.get(15)?,
+                odds_moved: false,
+            });
+        }
+        Ok(bouts)
+    }
+
+    // Deletes bouts (and everything recorded against them) that were settled more than `days` ago
+    // Bouts with bets on them are kept, as the ledger needs them
+    pub fn prune_settled(&mut self, days: u64) -> Result<(), Box<dyn Error>> {
+        let cutoff = now() - days as i64 * 86_400;
+        let prunable = "SELECT id FROM bouts WHERE settled_at < ?1 AND id NOT IN (SELECT bout_id FROM bets)";
+        let tx = self.conn.transaction()?;
+        for table in &["deliveries", "arbitrage_legs"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE notification_id IN
+                          (SELECT id FROM notifications WHERE bout_id IN ({}))", table, prunable),
+                params![cutoff],
+            )?;
+        }
+        for table in &["odds_snapshots", "model_outputs", "notifications"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE bout_id IN ({})", table, prunable),
+                params![cutoff],
+            )?;
+        }
+        let pruned = tx.execute(&format!("DELETE FROM bouts WHERE id IN ({})", prunable), params![cutoff])?;
+        tx.commit()?;
+        if pruned > 0 {
+            errln!("Pruned {} settled bout(s)", pruned);
+        }
+        Ok(())
+    }
+
+    // Inserts the bout if it's new (giving it an ID), otherwise updates it
+    pub fn save_bout(&self, metadata: &mut BoutMetadata) -> Result<i64, Box<dyn Error>> {
+        save_bout(&self.conn, metadata)
+    }
+
+    // Saves everything from a run in one go, so a crash part way through doesn't leave a mess
+    pub fn save_run(&mut self, boxers: &HashMap<String, Boxer>, bouts: &mut [BoutMetadata]) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.transaction()?;
+        for (alias, boxer) in boxers {
+            save_boxer(&tx, alias, boxer)?;
+        }
+        for metadata in bouts.iter_mut() {
+            save_bout(&tx, metadata)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn record_odds(&self, bout_id: i64, odds: &BoutOdds) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT INTO odds_snapshots (bout_id, taken_at, one_wins, draw, two_wins) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![bout_id, now(), odds.one_wins.as_frac(), odds.draw.as_frac(), odds.two_wins.as_frac()],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_model_output(&self, bout_id: i64, model: &str, matchup: &Matchup) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT INTO model_outputs (bout_id, model, computed_at, win_percent_one, win_percent_two, warning, score_one, score_two)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                bout_id,
+                model,
+                now(),
+                matchup.win_percent_one as f64,
+                matchup.win_percent_two as f64,
+                matchup.warning,
+                matchup.scores.map(|(one, _)| one as f64),
+                matchup.scores.map(|(_, two)| two as f64),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_bout(&self, id: i64) -> Result<Option<BoutSummary>, Box<dyn Error>> {
+        let bout = self.conn.query_row(
+            "SELECT id, fighter_one, fighter_two, status FROM bouts WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get::<_, String>(3)?)),
+        ).optional()?;
+        match bout {
+            Some((id, fighter_one, fighter_two, status)) => Ok(Some(BoutSummary {
+                id,
+                fighter_one,
+                fighter_two,
+                status: parse_status(&status)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    // Bouts (settled or not) where either fighter's name contains the query
+    pub fn find_bouts(&self, query: &str) -> Result<Vec<BoutSummary>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, fighter_one, fighter_two, status FROM bouts
+             WHERE fighter_one LIKE '%' || ?1 || '%' OR fighter_two LIKE '%' || ?1 || '%'
+             ORDER BY id"
+        )?;
+        let mut rows = statement.query(params![query])?;
+
+        let mut bouts = Vec::new();
+        while let Some(row) = rows.next()? {
+            bouts.push(BoutSummary {
+                id: row.get(0)?,
+                fighter_one: row.get(1)?,
+                fighter_two: row.get(2)?,
+                status: parse_status(&row.get::<_, String>(3)?)?,
+            });
+        }
+        Ok(bouts)
+    }
+
+    // Every snapshot of a bout's odds, oldest first
+    pub fn odds_history(&self, bout_id: i64) -> Result<Vec<(i64, BoutOdds)>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT taken_at, one_wins, draw, two_wins FROM odds_snapshots
+             WHERE bout_id = ?1 ORDER BY taken_at, id"
+        )?;
+        let mut rows = statement.query(params![bout_id])?;
+
+        let mut history = Vec::new();
+        while let Some(row) = rows.next()? {
+            history.push((row.get(0)?, BoutOdds {
+                one_wins: Odds::from_frac(&row.get::<_, String>(1)?)?,
+                draw: Odds::from_frac(&row.get::<_, String>(2)?)?,
+                two_wins: Odds::from_frac(&row.get::<_, String>(3)?)?,
+            }));
+        }
+        Ok(history)
+    }
+
+    // Every (computed_at, win_percent_one) a model has given for a bout, oldest first
+    pub fn model_history(&self, bout_id: i64, model: &str) -> Result<Vec<(i64, f32)>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT computed_at, win_percent_one FROM model_outputs
+             WHERE bout_id = ?1 AND model = ?2 ORDER BY computed_at, id"
+        )?;
+        let rows = statement.query_map(params![bout_id, model], |row| {
+            Ok((row.get(0)?, row.get::<_, f64>(1)? as f32))
+        })?;
+
+        let mut history = Vec::new();
+        for row in rows {
+            history.push(row?);
+        }
+        Ok(history)
+    }
+
+    // (id, winner) of every bout that's been settled with a result, in the order they were settled
+    pub fn completed_bouts(&self) -> Result<Vec<(i64, Winner)>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, winner FROM bouts
+             WHERE status = ?1 AND winner IS NOT NULL ORDER BY settled_at, id"
+        )?;
+        let mut rows = statement.query(params![status_key(&BoutStatus::Completed)])?;
+
+        let mut bouts = Vec::new();
+        while let Some(row) = rows.next()? {
+            bouts.push((row.get(0)?, parse_winner(&row.get::<_, String>(1)?)?));
+        }
+        Ok(bouts)
+    }
+
+    // Every model that's given an output for at least one bout
+    pub fn model_names(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare("SELECT DISTINCT model FROM model_outputs ORDER BY model")?;
+        let rows = statement.query_map(NO_PARAMS, |row| row.get(0))?;
+
+        let mut models = Vec::new();
+        for row in rows {
+            models.push(row?);
+        }
+        Ok(models)
+    }
+
+    // The most recent output a model gave for a bout
+    pub fn latest_model_output(&self, bout_id: i64, model: &str) -> Result<Option<ModelOutput>, Box<dyn Error>> {
+        let output = self.conn.query_row(
+            "SELECT win_percent_one, warning, score_one, score_two FROM model_outputs
+             WHERE bout_id = ?1 AND model = ?2 ORDER BY computed_at DESC, id DESC LIMIT 1",
+            params![bout_id, model],
+            |row| {
+                let score_one: Option<f64> = row.get(2)?;
+                let score_two: Option<f64> = row.get(3)?;
+                Ok(ModelOutput {
+                    win_percent_one: row.get::<_, f64>(0)? as f32,
+                    warning: row.get(1)?,
+                    scores: score_one.zip(score_two).map(|(one, two)| (one as f32, two as f32)),
+                })
+            },
+        ).optional()?;
+        Ok(output)
+    }
+
+    pub fn record_bet(&self, bout_id: i64, selection: &Winner, stake: f32, odds: &Odds, bookmaker: Option<&str>) -> Result<i64, Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT INTO bets (bout_id, selection, stake, odds, bookmaker, placed_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![bout_id, winner_key(selection), stake as f64, odds.as_frac(), bookmaker, now()],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    // Bets that haven't been settled yet, oldest first
+    pub fn open_bets(&self) -> Result<Vec<Bet>, Box<dyn Error>> {
+        self.query_bets("WHERE bets.outcome IS NULL ORDER BY bets.placed_at, bets.id")
+    }
+
+    // Bets that have been settled, in the order they were settled
+    pub fn settled_bets(&self) -> Result<Vec<Bet>, Box<dyn Error>> {
+        self.query_bets("WHERE bets.outcome IS NOT NULL ORDER BY bets.settled_at, bets.id")
+    }
+
+    // Settles any open bets on bouts that have been settled, returning the bets it settled
+    pub fn settle_bets(&mut self) -> Result<Vec<Bet>, Box<dyn Error>> {
+        let settleable = self.query_bets(
+            "WHERE bets.outcome IS NULL AND bouts.settled_at IS NOT NULL ORDER BY bets.placed_at, bets.id"
+        )?;
+        let mut settled = Vec::new();
+        let tx = self.conn.transaction()?;
+        for mut bet in settleable {
+            let (status, winner): (String, Option<String>) = tx.query_row(
+                "SELECT status, winner FROM bouts WHERE id = ?1",
+                params![bet.bout_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let winner = match winner {
+                Some(winner) => Some(parse_winner(&winner)?),
+                None => None,
+            };
+            let outcome = BetOutcome::from_result(&bet.selection, parse_status(&status)?, winner);
+            let profit = outcome.profit(bet.stake, &bet.odds);
+            let settled_at = now();
+            tx.execute(
+                "UPDATE bets SET outcome = ?1, profit = ?2, settled_at = ?3 WHERE id = ?4",
+                params![outcome_key(&outcome), profit as f64, settled_at, bet.id],
+            )?;
+            bet.outcome = Some(outcome);
+            bet.profit = Some(profit);
+            bet.settled_at = Some(settled_at);
+            settled.push(bet);
+        }
+        tx.commit()?;
+        Ok(settled)
+    }
+
+    fn query_bets(&self, filter: &str) -> Result<Vec<Bet>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT bets.id, bets.bout_id, bouts.fighter_one, bouts.fighter_two, bets.selection, bets.stake,
+                    bets.odds, bets.bookmaker, bets.placed_at, bets.outcome, bets.profit, bets.settled_at
+             FROM bets JOIN bouts ON bouts.id = bets.bout_id {}",
+            filter
+        ))?;
+        let mut rows = statement.query(NO_PARAMS)?;
+
+        let mut bets = Vec::new();
+        while let Some(row) = rows.next()? {
+            bets.push(Bet {
+                id: row.get(0)?,
+                bout_id: row.get(1)?,
+                fighter_one: row.get(2)?,
+                fighter_two: row.get(3)?,
+                selection: parse_winner(&row.get::<_, String>(4)?)?,
+                stake: row.get::<_, f64>(5)? as f32,
+                odds: Odds::from_frac(&row.get::<_, String>(6)?)?,
+                bookmaker: row.get(7)?,
+                placed_at: row.get(8)?,
+                outcome: match row.get::<_, Option<String>>(9)? {
+                    Some(outcome) => Some(parse_outcome(&outcome)?),
+                    None => None,
+                },
+                profit: row.get::<_, Option<f64>>(10)?.map(|profit| profit as f32),
+                settled_at: row.get(11)?,
+            });
+        }
+        Ok(bets)
+    }
+
+    pub fn record_notification(&self, bout_id: i64, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let (lay_stake, liability) = match alert.position {
+            Position::Lay { stake, liability } => (Some(stake as f64), Some(liability as f64)),
+            _ => (None, None),
+        };
+        let book_percent = match alert.position {
+            Position::Arbitrage { book_percent, .. } => Some(book_percent as f64),
+            _ => None,
+        };
+        self.conn.execute(
+            "INSERT INTO notifications (bout_id, sent_at, fighter, opponent, win_percent, odds, warning, fighter_score, opponent_score,
+                                        position, lay_stake, liability, source, book_percent)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![bout_id, alert.created_at, alert.fighter, alert.opponent, alert.win_percent.map(|percent| percent as f64),
+                    alert.odds.map(|odds| odds.as_frac()), alert.warning,
+                    alert.scores.map(|(fighter, _)| fighter as f64), alert.scores.map(|(_, opponent)| opponent as f64),
+                    alert.position.key(), lay_stake, liability, alert.source, book_percent],
+        )?;
+        let notification_id = self.conn.last_insert_rowid();
+        for leg in &alert.legs {
+            self.conn.execute(
+                "INSERT INTO arbitrage_legs (notification_id, selection, odds, source, stake, commission) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![notification_id, leg.selection, leg.odds.as_frac(), leg.source, leg.stake as f64, leg.commission as f64],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn arbitrage_legs(&self, notification_id: i64) -> Result<Vec<Leg>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT selection, odds, source, stake, commission FROM arbitrage_legs WHERE notification_id = ?1 ORDER BY rowid"
+        )?;
+        let mut rows = statement.query(params![notification_id])?;
+
+        let mut legs = Vec::new();
+        while let Some(row) = rows.next()? {
+            legs.push(Leg {
+                selection: row.get(0)?,
+                odds: Odds::from_frac(&row.get::<_, String>(1)?)?,
+                source: row.get(2)?,
+                stake: row.get::<_, f64>(3)? as f32,
+                commission: row.get::<_, f64>(4)? as f32,
+            });
+        }
+        Ok(legs)
+    }
+
+    // Notifications from after `since` which haven't been delivered to `sink` yet, oldest first
+    pub fn undelivered_alerts(&self, sink: &str, since: i64) -> Result<Vec<(i64, Alert)>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, sent_at, fighter, opponent, win_percent, odds, warning, fighter_score, opponent_score,
+                    position, lay_stake, liability, source, book_percent FROM notifications
+             WHERE sent_at >= ?1 AND id NOT IN (SELECT notification_id FROM deliveries WHERE sink = ?2)
+             ORDER BY sent_at, id"
+        )?;
+        let mut rows = statement.query(params![since, sink])?;
+
+        let mut alerts = Vec::new();
+        while let Some(row) = rows.next()? {
+            let id = row.get(0)?;
+            let position = row.get::<_, String>(9)?;
+            let legs = if position == "arbitrage" { self.arbitrage_legs(id)? } else { Vec::new() };
+            let position = match position.as_str() {
+                "back" => Position::Back,
+                "lay" => Position::Lay {
+                    stake: row.get::<_, f64>(10)? as f32,
+                    liability: row.get::<_, f64>(11)? as f32,
+                },
+                "arbitrage" => Position::Arbitrage {
+                    book_percent: row.get::<_, f64>(13)? as f32,
+                    outlay: arbitrage::outlay(&legs),
+                    profit: arbitrage::profit(&legs),
+                },
+                position => return Err(format!("Unknown position \"{}\" in database", position).into()),
+            };
+            alerts.push((id, Alert {
+                created_at: row.get(1)?,
+                fighter: row.get(2)?,
+                // Not recorded before schema version 6
+                opponent: row.get::<_, Option<String>>(3)?.unwrap_or_else(|| String::from("their opponent")),
+                // Both empty for arbitrages
+                win_percent: row.get::<_, Option<f64>>(4)?.map(|percent| percent as f32),
+                odds: match row.get::<_, Option<String>>(5)? {
+                    Some(odds) => Some(Odds::from_frac(&odds)?),
+                    None => None,
+                },
+                source: row.get(12)?,
+                position,
+                warning: row.get(6)?,
+                scores: match (row.get::<_, Option<f64>>(7)?, row.get::<_, Option<f64>>(8)?) {
+                    (Some(fighter), Some(opponent)) => Some((fighter as f32, opponent as f32)),
+                    _ => None,
+                },
+                legs,
+            }));
+        }
+        Ok(alerts)
+    }
+
+    pub fn record_delivery(&self, notification_id: i64, sink: &str) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO deliveries (notification_id, sink, delivered_at) VALUES (?1, ?2, ?3)",
+            params![notification_id, sink, now()],
+        )?;
+        Ok(())
+    }
+}
+
+fn save_boxer(conn: &Connection, alias: &str, boxer: &Boxer) -> Result<(), Box<dyn Error>> {
+    conn.execute(
+        "INSERT INTO boxers (id, forename, surname) VALUES (?1, ?2, ?3)
+         ON CONFLICT(id) DO UPDATE SET forename = excluded.forename, surname = excluded.surname",
+        params![boxer.get_id(), boxer.get_forename(), boxer.get_surname()],
+    )?;
+    conn.execute(
+        "INSERT INTO aliases (alias, boxer_id) VALUES (?1, ?2)
+         ON CONFLICT(alias) DO UPDATE SET boxer_id = excluded.boxer_id",
+        params![alias, boxer.get_id()],
+    )?;
+    Ok(())
+}
+
+fn save_bout(conn: &Connection, metadata: &mut BoutMetadata) -> Result<i64, Box<dyn Error>> {
+    let id = match metadata.id {
+        Some(id) => id,
+        None => {
+            conn.execute(
+                "INSERT INTO bouts (fighter_one, fighter_two, one_wins, draw, two_wins, status, first_seen)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    metadata.bout.fighter_one,
+                    metadata.bout.fighter_two,
+                    metadata.bout.odds.one_wins.as_frac(),
+                    metadata.bout.odds.draw.as_frac(),
+                    metadata.bout.odds.two_wins.as_frac(),
+                    status_key(&metadata.status),
+                    now(),
+                ],
+            )?;
+            conn.last_insert_rowid()
+        },
+    };
+    metadata.id = Some(id);
+
+    // Everything that can change over a bout's lifetime
+    conn.execute(
+        "UPDATE bouts SET status = ?1, event_link = ?2, event_date = ?3, winner = ?4, method = ?5,
+                          last_seen = ?6, settled_at = ?7, edge_side = ?8,
+                          one_wins = ?9, draw = ?10, two_wins = ?11, lay_side = ?12, arbitrage = ?13
+         WHERE id = ?14",
+        params![
+            status_key(&metadata.status),
+            metadata.event.as_ref().map(|event| event.link.clone()),
+            metadata.event.as_ref().and_then(|event| event.date).map(|date| date.format("%Y-%m-%d").to_string()),
+            metadata.result.as_ref().map(|result| winner_key(&result.winner)),
+            metadata.result.as_ref().and_then(|result| result.method.clone()),
+            metadata.last_seen,
+            metadata.settled_at,
+            metadata.edge_side.as_ref().map(side_key),
+            metadata.bout.odds.one_wins.as_frac(),
+            metadata.bout.odds.draw.as_frac(),
+            metadata.bout.odds.two_wins.as_frac(),
+            metadata.lay_side.as_ref().map(side_key),
+            metadata.arbitrage,
+            id,
+        ],
+    )?;
+    Ok(id)
+}
+
+pub fn status_key(status: &BoutStatus) -> &'static str {
+    match status {
+        BoutStatus::MissingBoxers => "missing_boxers",
+        BoutStatus::MissingBoutPage => "missing_bout_page",
+        BoutStatus::Checked => "checked",
+        BoutStatus::Announced => "announced",
+        BoutStatus::Completed => "completed",
+        BoutStatus::Cancelled => "cancelled",
+        BoutStatus::Expired => "expired",
+    }
+}
+
+fn parse_status(key: &str) -> Result<BoutStatus, Box<dyn Error>> {
+    match key {
+        "missing_boxers" => Ok(BoutStatus::MissingBoxers),
+        "missing_bout_page" => Ok(BoutStatus::MissingBoutPage),
+        "checked" => Ok(BoutStatus::Checked),
+        "announced" => Ok(BoutStatus::Announced),
+        "completed" => Ok(BoutStatus::Completed),
+        "cancelled" => Ok(BoutStatus::Cancelled),
+        "expired" => Ok(BoutStatus::Expired),
+        _ => Err(format!("Unknown bout status \"{}\" in database", key).into()),
+    }
+}
+
+fn side_key(side: &Side) -> &'static str {
+    match side {
+        Side::One => "one",
+        Side::Two => "two",
+    }
+}
+
+fn parse_side(key: &str) -> Result<Side, Box<dyn Error>> {
+    match key {
+        "one" => Ok(Side::One),
+        "two" => Ok(Side::Two),
+        _ => Err(format!("Unknown side \"{}\" in database", key).into()),
+    }
+}
+
+fn winner_key(winner: &Winner) -> &'static str {
+    match winner {
+        Winner::FighterOne => "one",
+        Winner::FighterTwo => "two",
+        Winner::Draw => "draw",
+        Winner::NoContest => "no_contest",
+    }
+}
+
+fn parse_winner(key: &str) -> Result<Winner, Box<dyn Error>> {
+    match key {
+        "one" => Ok(Winner::FighterOne),
+        "two" => Ok(Winner::FighterTwo),
+        "draw" => Ok(Winner::Draw),
+        "no_contest" => Ok(Winner::NoContest),
+        _ => Err(format!("Unknown winner \"{}\" in database", key).into()),
+    }
+}
+
+fn outcome_key(outcome: &BetOutcome) -> &'static str {
+    match outcome {
+        BetOutcome::Won => "won",
+        BetOutcome::Lost => "lost",
+        BetOutcome::Void => "void",
+    }
+}
+
+fn parse_outcome(key: &str) -> Result<BetOutcome, Box<dyn Error>> {
+    match key {
+        "won" => Ok(BetOutcome::Won),
+        "lost" => Ok(BetOutcome::Lost),
+        "void" => Ok(BetOutcome::Void),
+        _ => Err(format!("Unknown bet outcome \"{}\" in database", key).into()),
+    }
+}
+
+// True if SQLite doesn't recognise the file as a database, or it's corrupted
+fn is_unreadable(err: &(dyn Error + 'static)) -> bool {
+    match err.downcast_ref::<rusqlite::Error>() {
+        Some(rusqlite::Error::SqliteFailure(failure, _)) => matches!(
+            failure.code,
+            ErrorCode::NotADatabase | ErrorCode::DatabaseCorrupt
+        ),
+        _ => false,
+    }
+}
+
+// Seconds since the UNIX epoch
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    // How bouts.yml stored a bout, with fighter one at one_wins_top/1
+    fn legacy_bout(fighter_one: &str, fighter_two: &str, one_wins_top: u32, status: &str) -> String {
+        format!("- - fighter_one: {}\n    fighter_two: {}\n    odds:\n      one_wins: {{top: {}, bottom: 1}}\n      \
+                 draw: {{top: 20, bottom: 1}}\n      two_wins: {{top: 1, bottom: 1}}\n  - {}\n",
+                fighter_one, fighter_two, one_wins_top, status)
+    }
+
+    #[test]
+    fn yaml_import_keeps_only_the_newest_of_each_bout() {
+        let cache_path = env::temp_dir().join(format!("boxrec_tool_import_{}", std::process::id()));
+        fs::create_dir_all(&cache_path).unwrap();
+        let cache_path = cache_path.to_str().unwrap().to_string();
+        // A price change, then the same bout listed the other way round, and another bout
+        fs::write(format!("{}/bouts.yml", cache_path), [
+            legacy_bout("Alan Able", "Bob Baker", 1, "Checked"),
+            legacy_bout("Alan Able", "Bob Baker", 2, "Checked"),
+            legacy_bout("bob baker", "Alan Able", 3, "Announced"),
+            legacy_bout("Carl Cole", "Dan Dunn", 1, "Checked"),
+        ].concat()).unwrap();
+
+        let bouts = Storage::open(Some(&cache_path)).unwrap().load_bouts().unwrap();
+        fs::remove_dir_all(&cache_path).unwrap();
+
+        assert_eq!(bouts.len(), 2);
+        assert_eq!((bouts[0].bout.fighter_one.as_str(), bouts[0].bout.fighter_two.as_str()), ("bob baker", "Alan Able"));
+        assert_eq!(bouts[0].bout.odds.one_wins, Odds::from_frac("3/1").unwrap());
+        assert!(bouts[0].status == BoutStatus::Announced);
+        assert_eq!(bouts[1].bout.fighter_one, "Carl Cole");
+    }
+}
@@ -0,0 +1,334 @@
+use std::sync::mpsc::Sender;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::widgets::{ListState, TableState};
+
+use super::worker::{Job, PickRequest, Update};
+use crate::boxrec::Fight;
+use crate::output::ScanResult;
+
+// How many lines of the log are kept around to show
+const LOG_LINES: usize = 500;
+// Betfair's selections, in the order they're cycled through on the bet form
+pub const SELECTIONS: [&str; 3] = ["1", "X", "2"];
+
+pub struct App {
+    pub bouts: Vec<ScanResult>,
+    pub table: TableState,
+    pub threshold: f32,
+    // Bout the scan is on right now
+    pub checking: Option<i64>,
+    pub scanning: bool,
+    pub quitting: bool,
+    // Feedback from the last thing done, shown above the help line
+    pub message: String,
+    pub log: Vec<String>,
+    pub mode: Mode,
+    // Takes over the keyboard whenever it's set, as the scan is stuck until it's answered
+    pub pick: Option<(PickRequest, ListState)>,
+    // Same again while BoxRec wants a reCAPTCHA done
+    pub captcha: Option<Sender<bool>>,
+    jobs: Sender<Job>,
+}
+
+pub enum Mode {
+    Browse,
+    Profile(Profile),
+    Bet(BetForm),
+}
+
+pub struct Profile {
+    pub id: u32,
+    pub name: String,
+    // None while it's loading
+    pub fights: Option<Result<Vec<Fight>, String>>,
+    pub scroll: usize,
+}
+
+pub struct BetForm {
+    pub bout_id: i64,
+    pub fighters: (String, String),
+    // Index into SELECTIONS
+    pub selection: usize,
+    // Betfair's odds for each selection, for the odds field to start at
+    pub betfair_odds: [String; 3],
+    // Which source each of those odds came from, recorded as the bet's bookmaker
+    pub sources: [Option<String>; 3],
+    pub odds: String,
+    pub stake: String,
+    // 0 is the selection, 1 the odds and 2 the stake
+    pub field: usize,
+}
+
+impl App {
+    pub fn new(threshold: f32, jobs: Sender<Job>) -> App {
+        App {
+            bouts: Vec::new(),
+            table: TableState::default(),
+            threshold,
+            checking: None,
+            scanning: false,
+            quitting: false,
+            message: String::new(),
+            log: Vec::new(),
+            mode: Mode::Browse,
+            pick: None,
+            captcha: None,
+            jobs,
+        }
+    }
+
+    // True once it's safe to leave, i.e. nothing's half done
+    pub fn finished(&self) -> bool {
+        self.quitting && !self.scanning
+    }
+
+    pub fn start_scan(&mut self) {
+        if self.scanning {
+            self.message = String::from("Already scanning");
+            return;
+        }
+        if self.jobs.send(Job::Scan).is_ok() {
+            self.scanning = true;
+            self.message = String::from("Scanning Betfair");
+        }
+    }
+
+    pub fn push_log(&mut self, line: String) {
+        self.log.push(line);
+        if self.log.len() > LOG_LINES {
+            self.log.drain(..self.log.len() - LOG_LINES);
+        }
+    }
+
+    pub fn selected(&self) -> Option<&ScanResult> {
+        self.bouts.get(self.table.selected()?)
+    }
+
+    pub fn update(&mut self, update: Update) {
+        match update {
+            Update::BoutsFound(bouts) => {
+                self.bouts = bouts;
+                if self.table.selected().is_none_or(|index| index >= self.bouts.len()) {
+                    self.table.select(if self.bouts.is_empty() { None } else { Some(0) });
+                }
+                self.message = format!("Found {} bout(s)", self.bouts.len());
+            },
+            Update::Checking(bout_id) => self.checking = Some(bout_id),
+            Update::Checked(result) => {
+                match self.bouts.iter_mut().find(|bout| bout.bout_id == result.bout_id) {
+                    Some(bout) => *bout = *result,
+                    None => self.bouts.push(*result),
+                }
+                self.checking = None;
+            },
+            Update::ScanFinished(error) => {
+                self.scanning = false;
+                self.checking = None;
+                self.message = match error {
+                    Some(err) => format!("Scan failed: {}", err),
+                    None => format!("Scan finished at {}", chrono::Local::now().format("%H:%M")),
+                };
+            },
+            Update::Pick(request) => {
+                if self.quitting {
+                    // Nobody's going to answer it
+                    let _ = request.reply.send(None);
+                } else {
+                    let mut list = ListState::default();
+                    list.select(Some(0));
+                    self.pick = Some((request, list));
+                }
+            },
+            Update::Captcha(reply) => {
+                if self.quitting {
+                    let _ = reply.send(false);
+                } else {
+                    self.captcha = Some(reply);
+                }
+            },
+            Update::Profile(id, fights) => {
+                if let Mode::Profile(profile) = &mut self.mode {
+                    if profile.id == id {
+                        profile.fights = Some(fights);
+                    }
+                }
+            },
+            Update::BetPlaced(result) => {
+                self.message = match result {
+                    Ok(message) => message,
+                    Err(err) => format!("Couldn't record the bet: {}", err),
+                };
+            },
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        // Raw mode swallows Ctrl+C, so it's handled here
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit();
+            return;
+        }
+        if self.pick.is_some() {
+            self.handle_pick_key(key);
+            return;
+        }
+        if self.captcha.is_some() {
+            let done = match key.code {
+                KeyCode::Enter => true,
+                KeyCode::Esc => false,
+                _ => return,
+            };
+            let _ = self.captcha.take().unwrap().send(done);
+            return;
+        }
+
+        match &mut self.mode {
+            Mode::Browse => self.handle_browse_key(key),
+            Mode::Profile(profile) => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Backspace => self.mode = Mode::Browse,
+                KeyCode::Down | KeyCode::Char('j') => profile.scroll += 1,
+                KeyCode::Up | KeyCode::Char('k') => profile.scroll = profile.scroll.saturating_sub(1),
+                _ => (),
+            },
+            Mode::Bet(_) => self.handle_bet_key(key),
+        }
+    }
+
+    fn quit(&mut self) {
+        self.quitting = true;
+        if self.scanning {
+            self.message = String::from("Quitting once the scan's finished");
+        }
+        if let Some((request, _)) = self.pick.take() {
+            let _ = request.reply.send(None);
+        }
+        if let Some(reply) = self.captcha.take() {
+            let _ = reply.send(false);
+        }
+    }
+
+    fn handle_pick_key(&mut self, key: KeyEvent) {
+        let (request, list) = self.pick.as_mut().unwrap();
+        let answer = match key.code {
+            KeyCode::Down | KeyCode::Char('j') => {
+                list.select(Some((list.selected().unwrap_or(0) + 1).min(request.choices.len() - 1)));
+                return;
+            },
+            KeyCode::Up | KeyCode::Char('k') => {
+                list.select(Some(list.selected().unwrap_or(0).saturating_sub(1)));
+                return;
+            },
+            KeyCode::Enter => list.selected(),
+            KeyCode::Esc => None,
+            _ => return,
+        };
+        let (request, _) = self.pick.take().unwrap();
+        let _ = request.reply.send(answer);
+    }
+
+    fn handle_browse_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit(),
+            KeyCode::Char('s') => self.start_scan(),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Enter | KeyCode::Char('1') => self.open_profile(true),
+            KeyCode::Char('2') => self.open_profile(false),
+            KeyCode::Char('b') => self.open_bet_form(),
+            _ => (),
+        }
+    }
+
+    fn move_selection(&mut self, by: i64) {
+        if self.bouts.is_empty() {
+            return;
+        }
+        let current = self.table.selected().unwrap_or(0) as i64;
+        self.table.select(Some((current + by).clamp(0, self.bouts.len() as i64 - 1) as usize));
+    }
+
+    fn open_profile(&mut self, fighter_one: bool) {
+        let Some(bout) = self.selected() else { return };
+        let (name, id) = if fighter_one {
+            (bout.fighter_one.clone(), bout.boxrec_id_one)
+        } else {
+            (bout.fighter_two.clone(), bout.boxrec_id_two)
+        };
+        let Some(id) = id else {
+            self.message = format!("{} hasn't been found on BoxRec yet", name);
+            return;
+        };
+
+        if self.jobs.send(Job::Profile(id)).is_ok() {
+            if self.scanning {
+                self.message = String::from("The profile will load once the scan's finished");
+            }
+            self.mode = Mode::Profile(Profile { id, name, fights: None, scroll: 0 });
+        }
+    }
+
+    fn open_bet_form(&mut self) {
+        let Some(bout) = self.selected() else { return };
+        // Start on whichever fighter has the better edge, as that's what an alert is about
+        let selection = match (bout.edge_one, bout.edge_two) {
+            (Some(one), Some(two)) if two > one => 2,
+            _ => 0,
+        };
+        let betfair_odds = [bout.odds_one.clone(), bout.odds_draw.clone(), bout.odds_two.clone()];
+        self.mode = Mode::Bet(BetForm {
+            bout_id: bout.bout_id,
+            fighters: (bout.fighter_one.clone(), bout.fighter_two.clone()),
+            selection,
+            odds: betfair_odds[selection].clone(),
+            betfair_odds,
+            sources: [bout.source_one.clone(), bout.source_draw.clone(), bout.source_two.clone()],
+            stake: String::new(),
+            // Straight to the stake, the rest is usually right
+            field: 2,
+        });
+    }
+
+    fn handle_bet_key(&mut self, key: KeyEvent) {
+        let Mode::Bet(form) = &mut self.mode else { return };
+        match key.code {
+            KeyCode::Esc => self.mode = Mode::Browse,
+            KeyCode::Tab | KeyCode::Down => form.field = (form.field + 1) % 3,
+            KeyCode::BackTab | KeyCode::Up => form.field = (form.field + 2) % 3,
+            KeyCode::Left | KeyCode::Right if form.field == 0 => {
+                form.selection = if key.code == KeyCode::Left { (form.selection + 2) % 3 } else { (form.selection + 1) % 3 };
+                form.odds = form.betfair_odds[form.selection].clone();
+            },
+            KeyCode::Backspace => {
+                match form.field {
+                    1 => form.odds.pop(),
+                    2 => form.stake.pop(),
+                    _ => None,
+                };
+            },
+            KeyCode::Char(c) if form.field == 1 => form.odds.push(c),
+            KeyCode::Char(c) if form.field == 2 && (c.is_ascii_digit() || c == '.') => form.stake.push(c),
+            KeyCode::Enter => {
+                let stake = match form.stake.parse::<f32>() {
+                    Ok(stake) if stake > 0f32 => stake,
+                    _ => {
+                        self.message = String::from("Enter a stake more than 0");
+                        return;
+                    },
+                };
+                let job = Job::PlaceBet {
+                    bout_id: form.bout_id,
+                    selection: SELECTIONS[form.selection].to_string(),
+                    stake,
+                    odds: form.odds.clone(),
+                    bookmaker: form.sources[form.selection].clone(),
+                };
+                if self.jobs.send(job).is_ok() {
+                    self.message = String::from("Recording bet");
+                }
+                self.mode = Mode::Browse;
+            },
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute};
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+
+use crate::console;
+use crate::{Args, Config, Session};
+
+mod app;
+mod ui;
+mod worker;
+
+use app::App;
+
+// How long to wait for a key press before checking on the worker again
+const TICK: Duration = Duration::from_millis(100);
+
+pub fn run_tui(config: Config, args: &Args) -> Result<(), Box<dyn Error>> {
+    // Connect before taking over the terminal, as logging in may need to ask for a username/password
+    let session = match Session::open(&config, args)? {
+        Some(session) => session,
+        None => {
+            println!("Another run is already using the cache, exiting");
+            return Ok(());
+        },
+    };
+
+    // Everything the scan prints goes to a log file, and is shown in a pane rather than trampling the UI
+    let log_path = match &config.cache_path {
+        Some(cache_path) => format!("{}/tui.log", cache_path),
+        None => std::env::temp_dir().join("boxrec_tui.log").to_string_lossy().to_string(),
+    };
+    let log_file = OpenOptions::new().create(true).write(true).truncate(true).open(&log_path)?;
+    let mut log_reader = File::open(&log_path)?;
+
+    // The UI draws straight to the terminal, so it's kept apart from the messages
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(tty.try_clone()?))?;
+    let screen = Screen::enter(tty)?;
+    console::take_over(log_file);
+    terminal.clear()?;
+
+    let (jobs, job_queue) = mpsc::channel();
+    let (update_sender, updates) = mpsc::channel();
+    let mut app = App::new(config.get_notify_threshold(), jobs);
+
+    let result = thread::scope(|scope| -> Result<(), Box<dyn Error>> {
+        scope.spawn(|| worker::run_worker(&config, args, session, job_queue, update_sender));
+        app.start_scan();
+
+        let mut partial_line = String::new();
+        while !app.finished() {
+            while let Ok(update) = updates.try_recv() {
+                app.update(update);
+            }
+
+            let mut new_output = String::new();
+            // Read isn't imported, as it makes crossterm's macros ambiguous for File
+            io::Read::read_to_string(&mut log_reader, &mut new_output)?;
+            partial_line.push_str(&new_output);
+            while let Some(end) = partial_line.find('\n') {
+                let line: String = partial_line.drain(..=end).collect();
+                app.push_log(line.trim_end().to_string());
+            }
+
+            terminal.draw(|frame| ui::draw(frame, &mut app))?;
+
+            if event::poll(TICK)? {
+                if let Event::Key(key) = event::read()? {
+                    // Windows reports releases too
+                    if key.kind == KeyEventKind::Press {
+                        app.handle_key(key);
+                    }
+                }
+            }
+        }
+        // Hanging up lets the worker finish, which the scope waits for
+        drop(app);
+        Ok(())
+    });
+    drop(screen);
+    console::give_back();
+    result?;
+    println!("Log saved to {}", log_path);
+    Ok(())
+}
+
+// Raw mode and the alternate screen, put back however we leave
+struct Screen {
+    tty: File,
+}
+
+impl Screen {
+    fn enter(mut tty: File) -> Result<Screen, Box<dyn Error>> {
+        terminal::enable_raw_mode()?;
+        execute!(tty, EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen { tty })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(self.tty, LeaveAlternateScreen, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
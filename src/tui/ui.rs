@@ -0,0 +1,219 @@
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Clear, List, ListItem, Paragraph, Row, Table, Wrap};
+use ratatui::Frame;
+
+use super::app::{App, BetForm, Mode, Profile, SELECTIONS};
+use crate::output::ScanResult;
+
+// Same colour the report uses for value
+const VALUE_COLOUR: Color = Color::Green;
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [title, bouts, log, message, help] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(6),
+        Constraint::Length(8),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    let state = if app.scanning {
+        let done = app.bouts.iter().position(|bout| Some(bout.bout_id) == app.checking).unwrap_or(app.bouts.len());
+        format!("scanning ({}/{})", done, app.bouts.len())
+    } else {
+        String::from("idle")
+    };
+    frame.render_widget(Line::from(format!("BoxRec Heuristic - {}", state)).bold(), title);
+
+    draw_bouts(frame, app, bouts);
+
+    // Whatever the scan has printed, newest at the bottom
+    let shown = log.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = app.log.iter()
+        .skip(app.log.len().saturating_sub(shown))
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Log")), log);
+
+    frame.render_widget(Line::from(app.message.as_str()).fg(Color::Yellow), message);
+    let keys = if app.pick.is_some() {
+        "up/down choose  enter pick  esc skip"
+    } else if app.captcha.is_some() {
+        "enter done  esc give up"
+    } else {
+        match app.mode {
+            Mode::Browse => "up/down select  enter/1/2 boxer profile  b mark bet  s scan  q quit",
+            Mode::Profile(_) => "up/down scroll  esc back",
+            Mode::Bet(_) => "tab next field  left/right change selection  enter record  esc cancel",
+        }
+    };
+    frame.render_widget(Line::from(keys).fg(Color::DarkGray), help);
+
+    match &app.mode {
+        Mode::Browse => (),
+        Mode::Profile(profile) => draw_profile(frame, profile),
+        Mode::Bet(form) => draw_bet_form(frame, form),
+    }
+    // Drawn last so it's on top of everything else
+    if app.pick.is_some() {
+        draw_pick(frame, app);
+    } else if app.captcha.is_some() {
+        draw_captcha(frame);
+    }
+}
+
+fn draw_bouts(frame: &mut Frame, app: &mut App, area: Rect) {
+    let rows: Vec<Row> = app.bouts.iter()
+        .map(|bout| {
+            let checking = Some(bout.bout_id) == app.checking;
+            let value = bout.best_edge().is_some_and(|edge| edge > app.threshold);
+            let status = if checking {
+                String::from("Checking...")
+            } else {
+                bout.skip_reason.clone().unwrap_or_else(|| bout.status.to_string())
+            };
+            let style = if checking {
+                Style::default().fg(Color::Yellow)
+            } else if value {
+                Style::default().fg(VALUE_COLOUR).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                Cell::from(bout.bout_id.to_string()),
+                Cell::from(format!("{} vs. {}", bout.fighter_one, bout.fighter_two)),
+                Cell::from(match (bout.model_percent_one, bout.model_percent_two) {
+                    (Some(one), Some(two)) => format!("{:.1} / {:.1}", one, two),
+                    _ => String::from("-"),
+                }),
+                Cell::from(format!("{:.1} / {:.1}", bout.implied_percent_one, bout.implied_percent_two)),
+                Cell::from(format_edge(bout)),
+                Cell::from(status),
+            ]).style(style)
+        })
+        .collect();
+
+    let table = Table::new(rows, [
+        Constraint::Length(5),
+        Constraint::Percentage(35),
+        Constraint::Length(13),
+        Constraint::Length(13),
+        Constraint::Length(10),
+        Constraint::Fill(1),
+    ])
+        .header(Row::new(vec!["ID", "Bout", "Model %", "Betfair %", "Edge", "Status"]).bold())
+        .block(Block::bordered().title(format!("Bouts (green beats Betfair by more than {}%)", app.threshold)))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, area, &mut app.table);
+}
+
+// The better of the two fighters' edges, and whose it is
+fn format_edge(bout: &ScanResult) -> String {
+    match (bout.edge_one, bout.edge_two) {
+        (Some(one), Some(two)) if two > one => format!("{:+.1} (2)", two),
+        (Some(one), Some(_)) => format!("{:+.1} (1)", one),
+        _ => String::from("-"),
+    }
+}
+
+fn draw_profile(frame: &mut Frame, profile: &Profile) {
+    let area = popup(frame.area(), 80, 80);
+    frame.render_widget(Clear, area);
+    let block = Block::bordered().title(format!("{} (BoxRec ID {})", profile.name, profile.id));
+
+    let fights = match &profile.fights {
+        None => {
+            frame.render_widget(Paragraph::new("Loading...").block(block), area);
+            return;
+        },
+        Some(Err(err)) => {
+            frame.render_widget(Paragraph::new(format!("Couldn't load the profile: {}", err)).wrap(Wrap { trim: true }).block(block), area);
+            return;
+        },
+        Some(Ok(fights)) => fights,
+    };
+
+    let count = |result: &str| fights.iter().filter(|fight| fight.result == result).count();
+    let [record, history] = Layout::vertical([Constraint::Length(1), Constraint::Min(1)])
+        .areas(block.inner(area));
+    frame.render_widget(block, area);
+    frame.render_widget(
+        Line::from(format!("Record: {}-{}-{} (W-L-D), https://boxrec.com/en/proboxer/{}", count("W"), count("L"), count("D"), profile.id)),
+        record,
+    );
+
+    let rows: Vec<Row> = fights.iter()
+        .skip(profile.scroll.min(fights.len().saturating_sub(1)))
+        .map(|fight| {
+            let colour = match fight.result.as_str() {
+                "W" => Color::Green,
+                "L" => Color::Red,
+                "Scheduled" => Color::Yellow,
+                _ => Color::Reset,
+            };
+            Row::new(vec![
+                fight.date.map(|date| date.to_string()).unwrap_or_default(),
+                fight.opponent.clone(),
+                fight.result.clone(),
+                fight.method.clone().unwrap_or_default(),
+            ]).fg(colour)
+        })
+        .collect();
+    let table = Table::new(rows, [Constraint::Length(12), Constraint::Fill(1), Constraint::Length(10), Constraint::Length(6)])
+        .header(Row::new(vec!["Date", "Opponent", "Result", "Method"]).bold());
+    frame.render_widget(table, history);
+}
+
+fn draw_bet_form(frame: &mut Frame, form: &BetForm) {
+    let area = popup(frame.area(), 60, 40);
+    frame.render_widget(Clear, area);
+
+    let selection_name = match SELECTIONS[form.selection] {
+        "1" => form.fighters.0.as_str(),
+        "2" => form.fighters.1.as_str(),
+        _ => "Draw",
+    };
+    let field = |index: usize, label: &str, value: String| {
+        let line = Line::from(format!("{:<10}{}", label, value));
+        if index == form.field { line.reversed() } else { line }
+    };
+    let lines = vec![
+        Line::from(format!("{} vs. {}", form.fighters.0, form.fighters.1)).bold(),
+        Line::from(""),
+        field(0, "Backing", format!("< {} >", selection_name)),
+        field(1, "Odds", format!("{} ({}: {})", form.odds, form.sources[form.selection].as_deref().unwrap_or("Betfair"), form.betfair_odds[form.selection])),
+        field(2, "Stake", form.stake.clone()),
+    ];
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Mark bet")), area);
+}
+
+fn draw_pick(frame: &mut Frame, app: &mut App) {
+    let (request, list) = app.pick.as_mut().unwrap();
+    let area = popup(frame.area(), 60, 60);
+    frame.render_widget(Clear, area);
+
+    let items: Vec<ListItem> = request.choices.iter()
+        .map(|(name, id)| ListItem::new(format!("{} ({})", name, id)))
+        .collect();
+    let list_widget = List::new(items)
+        .block(Block::bordered().title(format!("No exact match for '{}', which boxer?", request.query)))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list_widget, area, list);
+}
+
+fn draw_captcha(frame: &mut Frame) {
+    let area = popup(frame.area(), 50, 20);
+    frame.render_widget(Clear, area);
+    let text = "BoxRec is prompting for a reCAPTCHA. Please visit the website and complete one under \
+                the login used by this program, then press enter";
+    frame.render_widget(Paragraph::new(text).wrap(Wrap { trim: true }).block(Block::bordered().title("reCAPTCHA")), area);
+}
+
+// A box in the middle of the screen, sized as a percentage of it
+fn popup(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Percentage(width)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(height)]).flex(Flex::Center).areas(area);
+    area
+}
@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::api;
+use crate::boxrec::{BoxerPicker, Fight};
+use crate::ledger;
+use crate::output::ScanResult;
+use crate::{scan_once, Args, Config, ScanObserver, Session};
+
+// Things the UI asks the worker to do, done one at a time in order
+pub enum Job {
+    Scan,
+    Profile(u32),
+    PlaceBet {
+        bout_id: i64,
+        selection: String,
+        stake: f32,
+        odds: String,
+        // The source the odds came from, if known
+        bookmaker: Option<String>,
+    },
+}
+
+// What the worker sends back
+pub enum Update {
+    BoutsFound(Vec<ScanResult>),
+    Checking(i64),
+    Checked(Box<ScanResult>),
+    // With the error, if the scan failed
+    ScanFinished(Option<String>),
+    Pick(PickRequest),
+    // BoxRec wants a reCAPTCHA done, reply true once it is or false to give up
+    Captcha(Sender<bool>),
+    Profile(u32, Result<Vec<Fight>, String>),
+    BetPlaced(Result<String, String>),
+}
+
+// A boxer search without an exact match, waiting on the user to choose (or skip with None)
+pub struct PickRequest {
+    pub query: String,
+    pub choices: Vec<(String, u32)>,
+    pub reply: Sender<Option<usize>>,
+}
+
+// Owns the session (and so BoxRec and the database) so the UI never has to wait on them
+pub fn run_worker(config: &Config, args: &Args, mut session: Session, jobs: Receiver<Job>, updates: Sender<Update>) {
+    session.boxrec.set_picker(Box::new(ChannelPicker { updates: updates.clone() }));
+    // Something to look at while Betfair's being scraped
+    if let Ok(bouts) = api::current_bouts(&session) {
+        let _ = updates.send(Update::BoutsFound(bouts));
+    }
+
+    // Finishes once the UI hangs up
+    for job in jobs {
+        let update = match job {
+            Job::Scan => {
+                let mut observer = ChannelObserver { updates: updates.clone() };
+                let result = scan_once(config, args, &mut session, &mut observer);
+                Update::ScanFinished(result.err().map(|err| err.to_string()))
+            },
+            Job::Profile(id) => Update::Profile(id, session.runtime.block_on(session.boxrec.get_fight_history(&id)).map_err(|err| err.to_string())),
+            Job::PlaceBet { bout_id, selection, stake, odds, bookmaker } => Update::BetPlaced(
+                ledger::place_bet(&mut session.storage, &bout_id.to_string(), &selection, stake, &odds, Some(bookmaker.as_deref().unwrap_or("Betfair")))
+                    .map(|()| format!("Recorded a bet of {:.2} at {} on bout {}", stake, odds, bout_id))
+                    .map_err(|err| err.to_string())
+            ),
+        };
+        if updates.send(update).is_err() {
+            break;
+        }
+    }
+}
+
+struct ChannelObserver {
+    updates: Sender<Update>,
+}
+
+// The UI may have gone by the time these are sent, in which case there's nobody to tell anyway
+impl ScanObserver for ChannelObserver {
+    fn bouts_found(&mut self, results: &[ScanResult]) {
+        let _ = self.updates.send(Update::BoutsFound(results.to_vec()));
+    }
+
+    fn checking(&mut self, bout_id: i64) {
+        let _ = self.updates.send(Update::Checking(bout_id));
+    }
+
+    fn checked(&mut self, result: &ScanResult) {
+        let _ = self.updates.send(Update::Checked(Box::new(result.clone())));
+    }
+}
+
+// Hands the choice over to the UI and waits for an answer
+struct ChannelPicker {
+    updates: Sender<Update>,
+}
+
+impl BoxerPicker for ChannelPicker {
+    fn pick(&mut self, query: &str, choices: &[(String, u32)]) -> Result<usize, Box<dyn Error>> {
+        let (reply, answer) = mpsc::channel();
+        self.updates.send(Update::Pick(PickRequest {
+            query: query.to_string(),
+            choices: choices.to_vec(),
+            reply,
+        }))?;
+        match answer.recv()? {
+            Some(choice) => Ok(choice),
+            None => Err("Skipped picking a boxer".into()),
+        }
+    }
+
+    fn captcha(&mut self) -> Result<(), Box<dyn Error>> {
+        let (reply, answer) = mpsc::channel();
+        self.updates.send(Update::Captcha(reply))?;
+        if answer.recv()? {
+            Ok(())
+        } else {
+            Err("Gave up on BoxRec's reCAPTCHA".into())
+        }
+    }
+}